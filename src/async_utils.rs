@@ -0,0 +1,195 @@
+use std::{
+    io::{self, ErrorKind},
+    net::IpAddr,
+    sync::Arc,
+};
+
+use tokio::{
+    net::{lookup_host, TcpListener, UdpSocket},
+    task::JoinSet,
+};
+
+use crate::{
+    error::{Errors, Result},
+    report::{ProbeFailure, ProbeOutcome},
+    utils::{bind_error, push_host, trace_failure, Host, OutcomePolicies},
+    Protocol,
+};
+
+/// Resolve a host to its addresses like `utils::resolve_host`, without blocking the runtime
+pub(crate) async fn resolve_host(host: &str) -> Result<Vec<Host>> {
+    if let Ok(ip_addr) = host.parse::<IpAddr>() {
+        return Ok(vec![ip_addr.into()]);
+    }
+    let resolution_error = |source| Errors::HostResolution {
+        host: host.to_string(),
+        source,
+    };
+    let mut ip_addrs = Vec::new();
+    for socket_addr in lookup_host((host, 0)).await.map_err(resolution_error)? {
+        push_host(&mut ip_addrs, socket_addr.into());
+    }
+    if ip_addrs.is_empty() {
+        return Err(resolution_error(io::Error::new(
+            ErrorKind::NotFound,
+            "no addresses found",
+        )));
+    }
+    Ok(ip_addrs)
+}
+
+/// Check whether no port can be bound on one of the hosts, like `utils::unprobeable` without blocking the runtime
+pub(crate) async fn any_unprobeable(hosts: &[Host], protocol: Protocol) -> bool {
+    for host in hosts {
+        let err = match protocol {
+            Protocol::Udp => UdpSocket::bind(host.socket_addr(0)).await.err(),
+            Protocol::Tcp | Protocol::All => TcpListener::bind(host.socket_addr(0)).await.err(),
+        };
+        let unprobeable = err.is_some_and(|err| {
+            matches!(ProbeOutcome::from(err.kind()), ProbeOutcome::Unprobeable(_))
+        });
+        if unprobeable {
+            return true;
+        }
+    }
+    false
+}
+
+/// Check concurrently if the ports are free in all hosts.
+/// Returns the first error found for each port, in the order of `ports`.
+pub(crate) async fn check_in_hosts(
+    ports: &[u16],
+    hosts: &Arc<Vec<Host>>,
+    protocol: Protocol,
+    policies: OutcomePolicies,
+) -> Vec<Option<ErrorKind>> {
+    let mut tasks = JoinSet::new();
+    for (index, port) in ports.iter().copied().enumerate() {
+        let hosts = Arc::clone(hosts);
+        tasks.spawn(async move { (index, first_failure(port, hosts, protocol, policies).await) });
+    }
+    let mut result = vec![Some(ErrorKind::Other); ports.len()];
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, failure)) = joined {
+            result[index] = failure;
+        }
+    }
+    result
+}
+
+/// Check concurrently if a port is free in all hosts
+pub(crate) async fn is_free_in_hosts(
+    port: u16,
+    hosts: Arc<Vec<Host>>,
+    protocol: Protocol,
+    policies: OutcomePolicies,
+) -> bool {
+    first_failure(port, hosts, protocol, policies)
+        .await
+        .is_none()
+}
+
+/// Check concurrently if a port is free in all hosts, returning the first error found if it is not
+pub(crate) async fn first_failure(
+    port: u16,
+    hosts: Arc<Vec<Host>>,
+    protocol: Protocol,
+    policies: OutcomePolicies,
+) -> Option<ErrorKind> {
+    let mut tasks = JoinSet::new();
+    for host in hosts.iter().copied() {
+        tasks.spawn(probe(port, host, protocol, policies));
+    }
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(None) => {}
+            Ok(Some(failure)) => {
+                trace_failure(port, &failure);
+                return Some(failure.kind);
+            }
+            Err(_) => return Some(ErrorKind::Other),
+        }
+    }
+    None
+}
+
+/// Check if a port is free, returning why it is not
+pub(crate) async fn probe(
+    port: u16,
+    host: Host,
+    protocol: Protocol,
+    policies: OutcomePolicies,
+) -> Option<ProbeFailure> {
+    let failure = match protocol {
+        Protocol::Tcp => probe_tcp(port, host, &policies)
+            .await
+            .map(|kind| (Protocol::Tcp, kind)),
+        Protocol::Udp => probe_udp(port, host, &policies)
+            .await
+            .map(|kind| (Protocol::Udp, kind)),
+        Protocol::All => match probe_tcp(port, host, &policies).await {
+            Some(kind) => Some((Protocol::Tcp, kind)),
+            None => probe_udp(port, host, &policies)
+                .await
+                .map(|kind| (Protocol::Udp, kind)),
+        },
+    };
+    failure.map(|(protocol, kind)| ProbeFailure {
+        host: host.ip,
+        scope_id: host.scope_id,
+        protocol,
+        kind,
+    })
+}
+
+/// Check if a TCP port is free, returning the error binding it failed with
+pub(crate) async fn probe_tcp(
+    port: u16,
+    host: Host,
+    policies: &OutcomePolicies,
+) -> Option<ErrorKind> {
+    bind_error(
+        TcpListener::bind(host.socket_addr(port)).await.err(),
+        policies,
+    )
+}
+
+/// Check if a UDP port is free, returning the error binding it failed with
+pub(crate) async fn probe_udp(
+    port: u16,
+    host: Host,
+    policies: &OutcomePolicies,
+) -> Option<ErrorKind> {
+    bind_error(
+        UdpSocket::bind(host.socket_addr(port)).await.err(),
+        policies,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::PortPicker;
+
+    #[tokio::test]
+    async fn test_pick_async() {
+        let picker = PortPicker::new().port_range(3000..=4000).concurrency(8);
+        let port = picker.pick_async().await.unwrap();
+        assert!((3000..=4000).contains(&port));
+        assert!(crate::is_free_async(port, None, Protocol::All).await);
+
+        let _listener = std::net::TcpListener::bind(("0.0.0.0", port)).unwrap();
+        assert!(!crate::is_free_async(port, None, Protocol::Tcp).await);
+        let unavailable = Some("192.0.2.1".to_string());
+        assert!(!crate::is_free_async(port, unavailable, Protocol::Udp).await);
+
+        let picker = PortPicker::new().host("localhost".to_string());
+        assert!(picker.pick_async().await.is_ok());
+        let picker = PortPicker::new().host("not a host".to_string());
+        let result = picker.pick_async().await;
+        assert!(matches!(
+            result,
+            Err(crate::error::Errors::HostResolution { .. })
+        ));
+    }
+}
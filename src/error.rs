@@ -1,12 +1,66 @@
 use thiserror::Error;
 
+use crate::utils::Host;
+
+/// Why picking a port failed.
+///
+/// New variants may be added, so matches need a wildcard arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Errors {
     #[error("{0}")]
     InvalidOption(String),
 
+    /// `pick_many` or `pick_block` found fewer free ports than requested.
     #[error("No available port")]
     NoAvailablePort,
+
+    #[error("No available port, {checked} ports checked and {excluded} excluded")]
+    RangeExhausted { checked: usize, excluded: usize },
+
+    #[error("All ports in the range are excluded")]
+    AllExcluded,
+
+    /// Every port checked failed to bind with `PermissionDenied`.
+    #[error("Permission denied binding all of the {checked} ports checked")]
+    PermissionDenied { checked: usize },
+
+    /// Probing a port returned an outcome the policy of the picker fails the pick on.
+    #[error("Probing port {port} returned: {outcome}")]
+    ProbeFailed {
+        port: u16,
+        outcome: crate::ProbeOutcome,
+    },
+
+    /// Privileged ports were allowed, but the process cannot bind them.
+    #[error("The process cannot bind port {port}, only ports from {lowest} up. Run as root, grant CAP_NET_BIND_SERVICE or lower net.ipv4.ip_unprivileged_port_start")]
+    PrivilegedPort { port: u16, lowest: u16 },
+
+    /// The host is neither an IP address nor a name that resolves to one.
+    #[error("Failed to resolve the host {host}")]
+    HostResolution {
+        host: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// No port can be bound on the host, e.g. the address is not available.
+    /// `scope_id` is the index of the interface scoping an Ipv6 link-local host, 0 for other hosts.
+    #[error("The host {} cannot be probed, binding it failed with: {kind}", Host::scoped(*.host, *.scope_id))]
+    Unprobeable {
+        host: std::net::IpAddr,
+        scope_id: u32,
+        kind: std::io::ErrorKind,
+    },
+
+    #[error("Failed to list the network interfaces")]
+    Interfaces(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to prepare the lease registry")]
+    LeaseRegistry(#[source] std::io::Error),
+
+    #[error("Failed to read the socket tables")]
+    SocketTable(#[source] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Errors>;
@@ -0,0 +1,118 @@
+use std::{collections::HashSet, fmt, net::SocketAddr};
+
+use crate::{
+    error::{Errors, Result},
+    procfs::{self, SocketTable},
+    Protocol,
+};
+
+/// The state of a socket, as reported by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketState {
+    Established,
+    SynSent,
+    SynRecv,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Close,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    Unknown(u8),
+}
+
+impl From<u8> for SocketState {
+    fn from(state: u8) -> Self {
+        match state {
+            0x01 => SocketState::Established,
+            0x02 => SocketState::SynSent,
+            0x03 => SocketState::SynRecv,
+            0x04 => SocketState::FinWait1,
+            0x05 => SocketState::FinWait2,
+            0x06 => SocketState::TimeWait,
+            0x07 => SocketState::Close,
+            0x08 => SocketState::CloseWait,
+            0x09 => SocketState::LastAck,
+            0x0A => SocketState::Listen,
+            0x0B => SocketState::Closing,
+            other => SocketState::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for SocketState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketState::Established => write!(f, "ESTABLISHED"),
+            SocketState::SynSent => write!(f, "SYN_SENT"),
+            SocketState::SynRecv => write!(f, "SYN_RECV"),
+            SocketState::FinWait1 => write!(f, "FIN_WAIT1"),
+            SocketState::FinWait2 => write!(f, "FIN_WAIT2"),
+            SocketState::TimeWait => write!(f, "TIME_WAIT"),
+            SocketState::Close => write!(f, "CLOSE"),
+            SocketState::CloseWait => write!(f, "CLOSE_WAIT"),
+            SocketState::LastAck => write!(f, "LAST_ACK"),
+            SocketState::Listen => write!(f, "LISTEN"),
+            SocketState::Closing => write!(f, "CLOSING"),
+            SocketState::Unknown(state) => write!(f, "UNKNOWN({:02X})", state),
+        }
+    }
+}
+
+/// A socket using a port, and the process holding it if it could be found
+#[derive(Debug, Clone)]
+pub struct PortInfo {
+    /// `Protocol::Tcp` or `Protocol::Udp`.
+    pub protocol: Protocol,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub state: SocketState,
+    pub inode: u64,
+    /// The owning process, only known if its descriptors are readable by the current user.
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+}
+
+impl fmt::Display for PortInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let protocol = match self.protocol {
+            Protocol::Udp => "udp",
+            _ => "tcp",
+        };
+        write!(f, "{} {} {}", protocol, self.local, self.state)?;
+        match (&self.process, self.pid) {
+            (Some(process), Some(pid)) => write!(f, " held by {} (pid {})", process, pid),
+            (None, Some(pid)) => write!(f, " held by pid {}", pid),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Read the sockets using `port` from the kernel's socket tables
+pub(crate) fn port_info(port: u16) -> Result<Vec<PortInfo>> {
+    let table = SocketTable::read().map_err(Errors::SocketTable)?;
+    let entries: Vec<_> = table.entries_for(port).collect();
+    let inodes: HashSet<u64> = entries
+        .iter()
+        .map(|entry| entry.inode)
+        .filter(|inode| *inode != 0)
+        .collect();
+    let owners = procfs::socket_owners(&inodes);
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let pid = owners.get(&entry.inode).copied();
+            PortInfo {
+                protocol: entry.protocol,
+                local: entry.local,
+                remote: entry.remote,
+                state: entry.state.into(),
+                inode: entry.inode,
+                pid,
+                process: pid.and_then(procfs::process_name),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,321 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long a lock file that cannot be parsed is honored, e.g. one left empty by a crash
+const UNREADABLE_GRACE: Duration = Duration::from_secs(10);
+
+/// Makes the temporary file names and lock contents of this process unique
+static NONCE: AtomicU64 = AtomicU64::new(0);
+
+/// The state of a lock file
+#[derive(Debug, PartialEq, Eq)]
+enum Lock {
+    Missing,
+    Live,
+    /// The lease expired, its owner died, or the file has been unreadable for longer than the grace period.
+    Stale,
+}
+
+/// A registry of port leases shared between processes through lock files on the local filesystem.
+///
+/// Each leased port is recorded as a `<port>.lock` file holding the owning PID and the expiry time.
+/// A lease is honored until it expires or, on Linux, until the owning process dies.
+///
+/// #Examples:
+///
+/// ```
+/// use random_port::{LeaseRegistry, PortPicker};
+/// let port = PortPicker::new()
+///     .lease_registry(LeaseRegistry::default())
+///     .pick()
+///     .unwrap();
+/// println!("The leased port is {}", port);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LeaseRegistry {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl LeaseRegistry {
+    /// Creates a registry storing its lock files in `dir`. The directory is created if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LeaseRegistry {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Specifies how long a lease is honored, Default is 10 minutes.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Returns the directory holding the lock files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Releases the lease on `port` if it is held by the current process.
+    pub fn release(&self, port: u16) -> io::Result<()> {
+        let path = self.lock_path(port);
+        match read_lease(&path) {
+            Some((pid, _)) if pid == process::id() => fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether `port` is held by a live lease.
+    pub fn is_leased(&self, port: u16) -> bool {
+        read_lock(&self.lock_path(port)) == Lock::Live
+    }
+
+    pub(crate) fn prepare(&self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)
+    }
+
+    /// Leases `port` to the current process. Returns `false` if it is held by a live lease.
+    ///
+    /// A stale lock is reclaimed under a `<port>.reclaim` file taken as a mutex, so that only one of several
+    /// processes reclaiming it wins. The lock is read again under the mutex and replaced in one step,
+    /// so it is never missing while it is reclaimed.
+    pub(crate) fn acquire(&self, port: u16) -> bool {
+        let path = self.lock_path(port);
+        if self.create(&path).is_ok() {
+            return true;
+        }
+        match read_lock(&path) {
+            Lock::Live => false,
+            Lock::Missing => self.create(&path).is_ok(),
+            Lock::Stale => self.reclaim(port, &path),
+        }
+    }
+
+    fn reclaim(&self, port: u16, path: &Path) -> bool {
+        let mutex = self.dir.join(format!("{}.reclaim", port));
+        if self.create_file(&mutex, "").is_err() {
+            // The owner of a mutex left behind by a crash is gone, it is removed for the next attempt
+            if is_older_than(&mutex, UNREADABLE_GRACE) {
+                let _ = fs::remove_file(&mutex);
+            }
+            return false;
+        }
+        let reclaimed = match read_lock(path) {
+            Lock::Live => false,
+            Lock::Missing => self.create(path).is_ok(),
+            Lock::Stale => self.replace(path).is_ok(),
+        };
+        let _ = fs::remove_file(&mutex);
+        reclaimed
+    }
+
+    /// Creates the lock file with its content in one step, failing if the lock exists.
+    fn create(&self, path: &Path) -> io::Result<()> {
+        self.create_file(path, &self.lease_content())
+    }
+
+    /// Replaces the lock file with a lease of the current process in one step.
+    fn replace(&self, path: &Path) -> io::Result<()> {
+        let temp = self.write_temp(&self.lease_content())?;
+        let result = fs::rename(&temp, path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// Creates a file with its content in one step:
+    /// the content is written to a temporary file which is then hard linked, failing if the file exists.
+    fn create_file(&self, path: &Path, content: &str) -> io::Result<()> {
+        let temp = self.write_temp(content)?;
+        let result = fs::hard_link(&temp, path);
+        let _ = fs::remove_file(&temp);
+        result
+    }
+
+    fn write_temp(&self, content: &str) -> io::Result<PathBuf> {
+        let temp = self.unique_path();
+        let result =
+            fs::File::create(&temp).and_then(|mut file| file.write_all(content.as_bytes()));
+        match result {
+            Ok(()) => Ok(temp),
+            Err(err) => {
+                let _ = fs::remove_file(&temp);
+                Err(err)
+            }
+        }
+    }
+
+    fn lease_content(&self) -> String {
+        let expires_at = now_secs().saturating_add(self.ttl.as_secs());
+        let nonce = NONCE.fetch_add(1, Ordering::Relaxed);
+        format!("{} {} {}\n", process::id(), expires_at, nonce)
+    }
+
+    /// A temporary file name unique to this process and call, which is not mistaken for a lock
+    fn unique_path(&self) -> PathBuf {
+        let nonce = NONCE.fetch_add(1, Ordering::Relaxed);
+        self.dir.join(format!(".{}.{}.tmp", process::id(), nonce))
+    }
+
+    fn lock_path(&self, port: u16) -> PathBuf {
+        self.dir.join(format!("{}.lock", port))
+    }
+}
+
+impl Default for LeaseRegistry {
+    /// Uses `$XDG_RUNTIME_DIR/random-port`, or `random-port` in the temporary directory if it is not set.
+    fn default() -> Self {
+        let base = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        LeaseRegistry::new(base.join("random-port"))
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Reads the owning PID and expiry time of a lock file, `None` if it is missing or cannot be parsed.
+fn read_lease(path: &Path) -> Option<(u32, u64)> {
+    parse_lease(&fs::read_to_string(path).ok()?)
+}
+
+fn parse_lease(content: &str) -> Option<(u32, u64)> {
+    let mut parts = content.split_whitespace();
+    let pid = parts.next()?.parse().ok()?;
+    let expires_at = parts.next()?.parse().ok()?;
+    Some((pid, expires_at))
+}
+
+/// Reads the state of a lock file.
+/// A lock file that cannot be parsed is honored until it is older than the grace period.
+fn read_lock(path: &Path) -> Lock {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Lock::Missing,
+        Err(_) => String::new(),
+    };
+    let stale = match parse_lease(&content) {
+        Some((pid, expires_at)) => is_stale(pid, expires_at),
+        None => is_older_than(path, UNREADABLE_GRACE),
+    };
+    if stale {
+        Lock::Stale
+    } else {
+        Lock::Live
+    }
+}
+
+/// Whether a file was last modified longer than `age` ago
+fn is_older_than(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|elapsed| elapsed > age)
+}
+
+fn is_stale(pid: u32, expires_at: u64) -> bool {
+    expires_at <= now_secs() || !is_alive(pid)
+}
+
+#[cfg(target_os = "linux")]
+fn is_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = env::temp_dir().join(format!("random-port-lease-{}", process::id()));
+        let registry = LeaseRegistry::new(&dir);
+        registry.prepare().unwrap();
+
+        assert!(registry.acquire(3000));
+        assert!(registry.is_leased(3000));
+        assert!(!registry.acquire(3000));
+        registry.release(3000).unwrap();
+        assert!(!registry.is_leased(3000));
+        assert!(registry.acquire(3000));
+
+        // An expired lease is reclaimed
+        fs::write(registry.lock_path(3001), format!("{} 0\n", process::id())).unwrap();
+        assert!(!registry.is_leased(3001));
+        assert!(registry.acquire(3001));
+
+        // A lock file that is not written yet is honored until the grace period passed
+        let path = registry.lock_path(3002);
+        let file = fs::File::create(&path).unwrap();
+        assert!(registry.is_leased(3002));
+        assert!(!registry.acquire(3002));
+        file.set_modified(SystemTime::now() - 2 * UNREADABLE_GRACE)
+            .unwrap();
+        assert!(!registry.is_leased(3002));
+        assert!(registry.acquire(3002));
+
+        // Of several concurrent reclaims of a stale lease only one wins
+        fs::write(registry.lock_path(3003), format!("{} 0\n", process::id())).unwrap();
+        let won = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| registry.acquire(3003)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .filter(|won| *won)
+                .count()
+        });
+        assert_eq!(won, 1);
+
+        // A reclaim in progress is waited for, one left behind by a crash is removed after the grace period
+        fs::write(registry.lock_path(3004), format!("{} 0\n", process::id())).unwrap();
+        let mutex = fs::File::create(dir.join("3004.reclaim")).unwrap();
+        assert!(!registry.acquire(3004));
+        mutex
+            .set_modified(SystemTime::now() - 2 * UNREADABLE_GRACE)
+            .unwrap();
+        assert!(!registry.acquire(3004));
+        assert!(registry.acquire(3004));
+
+        // Only the lock files are left behind
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "3000.lock",
+                "3001.lock",
+                "3002.lock",
+                "3003.lock",
+                "3004.lock"
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,20 +1,108 @@
 use crate::error::{Errors, Result};
-use rand::prelude::*;
-use std::{collections::HashSet, net::IpAddr, ops::RangeInclusive};
+use crate::utils::{Host, InterfaceFilter, OutcomePolicies, Prober, Tally};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use std::{
+    collections::HashSet,
+    env,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::RangeInclusive,
+    sync::Mutex,
+};
 
+#[cfg(feature = "tokio")]
+mod async_utils;
 pub mod error;
+mod info;
+mod lease;
+mod permutation;
+mod port_set;
+mod procfs;
+mod report;
+mod reserved;
+mod services;
+mod strategy;
+mod sysctl;
 mod utils;
 
+pub use info::{PortInfo, SocketState};
+pub use lease::LeaseRegistry;
+pub use port_set::PortSet;
+pub use report::{Attempt, PickReport, PickSource, Picked, ProbeFailure, ProbeOutcome};
+pub use reserved::ReservedPort;
+pub use strategy::{ClosestTo, Descending, Random, RoundRobin, SelectionStrategy, Sequential};
+
 const MIN_PORT: u16 = 1024;
 const MAX_PORT: u16 = 65535;
 
+/// How many ports `pick_from_os` asks the OS for before giving up
+const OS_ASSIGNED_ATTEMPTS: usize = 32;
+
+/// The environment variable overriding the seed of random picking, e.g. `RANDOM_PORT_SEED=42`
+pub const SEED_ENV: &str = "RANDOM_PORT_SEED";
+
 //
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Protocol {
     All,
     Tcp,
     Udp,
 }
 
+/// How to detect whether a port is in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detection {
+    /// Try to bind the port on every host.
+    Bind,
+    /// Read the kernel's socket tables in `/proc/net` once per pick. Falls back to `Bind` if they cannot be read.
+    Procfs,
+    /// A port must be free in the socket tables and bindable on every host.
+    Both,
+}
+
+/// How to treat the kernel's ephemeral port range, from which outgoing connections get their source ports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EphemeralPolicy {
+    /// Pick from the whole range.
+    Ignore,
+    /// Never pick a port in the ephemeral range, as an outgoing connection could take it before it is bound.
+    Avoid,
+    /// Only pick ports in the ephemeral range.
+    Only,
+}
+
+/// Which address families to check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Check Ipv4 and Ipv6 addresses.
+    Any,
+    /// Only check Ipv4 addresses.
+    Ipv4,
+    /// Only check Ipv6 addresses.
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn contains(&self, ip_addr: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::Ipv4 => ip_addr.is_ipv4(),
+            AddressFamily::Ipv6 => ip_addr.is_ipv6(),
+        }
+    }
+}
+
+/// How a probe outcome that is neither free nor in use affects the pick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutcomePolicy {
+    /// The port counts as free on the host.
+    Free,
+    /// The port counts as in use, and the next candidate is tried.
+    InUse,
+    /// The pick fails with `Errors::ProbeFailed`.
+    Fail,
+}
+
 /// PortPicker is a simple library to pick a free port in the local machine.
 ///
 /// It can be used to find a free port to start a server or any other use case.
@@ -27,27 +115,68 @@ pub enum Protocol {
 /// println!("The free port is {}", port);
 /// ```
 pub struct PortPicker {
-    range: RangeInclusive<u16>,
+    ports: PortSet,
     exclude: HashSet<u16>,
     protocol: Protocol,
-    host: Option<String>,
-    random: bool,
+    hosts: Vec<String>,
+    family: AddressFamily,
+    policies: OutcomePolicies,
+    interfaces: InterfaceFilter,
+    strategy: Box<dyn SelectionStrategy>,
+    preferred: Vec<u16>,
+    align: u16,
+    lease: Option<LeaseRegistry>,
+    threads: usize,
+    detection: Detection,
+    ephemeral: EphemeralPolicy,
+    exclude_reserved: bool,
+    exclude_services: bool,
+    privileged: bool,
+    seed: Option<u64>,
+    rng: Option<Mutex<Box<dyn RngCore + Send>>>,
+    last_seed: Mutex<Option<u64>>,
+    #[cfg(feature = "tokio")]
+    concurrency: usize,
 }
 
 impl PortPicker {
     pub fn new() -> Self {
         PortPicker {
-            range: MIN_PORT..=MAX_PORT,
+            ports: PortSet::from(MIN_PORT..=MAX_PORT),
             exclude: HashSet::new(),
             protocol: Protocol::All,
-            host: None,
-            random: false,
+            hosts: Vec::new(),
+            family: AddressFamily::Any,
+            policies: OutcomePolicies::default(),
+            interfaces: InterfaceFilter::default(),
+            strategy: Box::new(Sequential),
+            preferred: Vec::new(),
+            align: 1,
+            lease: None,
+            threads: 1,
+            detection: Detection::Bind,
+            ephemeral: EphemeralPolicy::Ignore,
+            exclude_reserved: false,
+            exclude_services: false,
+            privileged: false,
+            seed: None,
+            rng: None,
+            last_seed: Mutex::new(None),
+            #[cfg(feature = "tokio")]
+            concurrency: 64,
         }
     }
 
-    /// Specifies the range of ports to check. Must be in the range `1024..=65535`. E.g. `port_range(1024..=65535)`.
+    /// Specifies the range of ports to check. Must be in the range `1024..=65535` unless `allow_privileged` is set. E.g. `port_range(1024..=65535)`.
     pub fn port_range(mut self, range: RangeInclusive<u16>) -> Self {
-        self.range = range;
+        self.ports = PortSet::from(range);
+        self
+    }
+
+    /// Specifies the set of ports to check, made of several ranges and excluded ranges. Must be in the range `1024..=65535` unless `allow_privileged` is set.
+    /// Replaces the range. E.g. `port_set("20000-29999,40000-44999,!25000-25999".parse()?)`.
+    pub fn port_set(mut self, ports: PortSet) -> Self {
+        self.ports = ports;
         self
     }
 
@@ -69,100 +198,865 @@ impl PortPicker {
         self
     }
 
-    /// Specifies the host to check. Can be either an Ipv4 or Ipv6 address.
+    /// Specifies the host to check. Can be an Ipv4 or Ipv6 address, or a hostname such as `localhost`,
+    /// in which case every address it resolves to is checked.
     /// If not specified, will checks availability on all local addresses defined in the system.
     pub fn host(mut self, host: String) -> Self {
-        self.host = Some(host);
+        self.hosts = vec![host];
+        self
+    }
+
+    /// Specifies several hosts to check, e.g. `hosts(["127.0.0.1", "::1"])`. A port must be free on all of them.
+    /// Replaces the host. `pick_from_os` binds the first of them.
+    pub fn hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Specifies which address families to check, Default is `AddressFamily::Any`.
+    /// Filters both the local addresses and the addresses the hosts resolve to.
+    pub fn address_family(mut self, family: AddressFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Specifies how a host on which no port can be bound affects the pick, Default is `OutcomePolicy::Free`.
+    ///
+    /// Before picking, port 0 is bound on every host to find them. With `OutcomePolicy::Free` such a host is left out
+    /// and listed in `PickReport::unprobeable`, otherwise the pick fails with `Errors::Unprobeable`.
+    /// Should an address become unavailable while picking, its ports count as free, in use, or fail the pick
+    /// with `Errors::ProbeFailed`. Picking always fails with `Errors::Unprobeable` if no host can be probed.
+    pub fn unprobeable_ports(mut self, policy: OutcomePolicy) -> Self {
+        self.policies.unprobeable = policy;
+        self
+    }
+
+    /// Specifies how a port the process is not allowed to bind affects the pick, Default is `OutcomePolicy::InUse`.
+    pub fn denied_ports(mut self, policy: OutcomePolicy) -> Self {
+        self.policies.denied = policy;
+        self
+    }
+
+    /// Specifies the network interfaces to check by name or glob pattern, e.g. `["lo", "eth*"]`.
+    /// Only applies when no host is specified. The unspecified addresses `0.0.0.0` and `::` are then not checked.
+    pub fn interfaces<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.interfaces.include = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Specifies network interfaces not to check by name or glob pattern, e.g. `["veth*", "docker0"]`.
+    /// Only applies when no host is specified. The unspecified addresses `0.0.0.0` and `::` are then not checked.
+    pub fn exclude_interfaces<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.interfaces.exclude = names.into_iter().map(Into::into).collect();
         self
     }
 
     /// Specifies whether to pick a random port from the range.
+    /// The range is walked in a random order that tries every port once, so any free port may be picked.
     /// If not specified, will pick the first available port from the range.
+    ///
+    /// A shorthand for `strategy(Random)` or `strategy(Sequential)`.
     pub fn random(mut self, random: bool) -> Self {
-        self.random = random;
+        self.strategy = if random {
+            Box::new(Random)
+        } else {
+            Box::new(Sequential)
+        };
         self
     }
 
-    fn random_port(&self, ip_addrs: HashSet<IpAddr>) -> Result<u16> {
-        let mut rng = rand::thread_rng();
-        let len = self.range.len();
-        for _ in 0..len {
-            let port = rng.gen_range(*self.range.start()..=*self.range.end());
-            if self.exclude.contains(&port) {
-                continue;
-            }
-            if utils::is_free_in_hosts(port, &ip_addrs, &self.protocol) {
-                return Ok(port);
+    /// Specifies the order in which the ports of the range are tried, Default is `Sequential`.
+    /// Can be one of `Sequential`, `Random`, `Descending`, `RoundRobin`, `ClosestTo`, or a custom `SelectionStrategy`.
+    pub fn strategy(mut self, strategy: impl SelectionStrategy + 'static) -> Self {
+        self.strategy = Box::new(strategy);
+        self
+    }
+
+    /// Specifies the ports to try in order before the range. The first one is the exact preference, the others are fallbacks.
+    /// They may be outside the range, but not excluded. E.g. `prefer([8080, 8081, 8082])`.
+    pub fn prefer(mut self, ports: impl IntoIterator<Item = u16>) -> Self {
+        self.preferred = ports.into_iter().collect();
+        self
+    }
+
+    /// Specifies the seed of random picking, so the same ports are tried in the same order on every run.
+    /// If not specified, the seed is read from the `RANDOM_PORT_SEED` environment variable, or drawn at random.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Specifies the random number generator of random picking, which takes precedence over the seed.
+    pub fn rng(mut self, rng: impl RngCore + Send + 'static) -> Self {
+        self.rng = Some(Mutex::new(Box::new(rng)));
+        self
+    }
+
+    /// Returns the seed used by the last random pick, to replay it with `seed`.
+    /// Returns `None` if no random pick was made or a custom generator is used.
+    pub fn last_seed(&self) -> Option<u64> {
+        *self.last_seed.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Specifies the alignment of the first port of a block picked by `pick_block`, Default is `1`.
+    /// E.g. `align(10)` only returns blocks starting on a multiple of 10.
+    pub fn align(mut self, align: u16) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Specifies a lease registry shared with other processes.
+    /// Picked ports are leased in the registry, and ports leased by live processes are never picked.
+    pub fn lease_registry(mut self, registry: LeaseRegistry) -> Self {
+        self.lease = Some(registry);
+        self
+    }
+
+    /// Specifies how many threads probe ports in parallel, Default is `1`.
+    /// Ports are still returned in candidate order, so sequential picking returns the first available port.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Specifies how to detect whether a port is in use, Default is `Detection::Bind`.
+    /// `Detection::Procfs` makes scanning large ranges much faster on Linux and also sees ports held in `TIME_WAIT`.
+    pub fn detection(mut self, detection: Detection) -> Self {
+        self.detection = detection;
+        self
+    }
+
+    /// Specifies how to treat the kernel's ephemeral port range, Default is `EphemeralPolicy::Ignore`.
+    /// On Linux the range is read from `/proc/sys/net/ipv4/ip_local_port_range`,
+    /// otherwise or if it cannot be read, the platform default is used.
+    pub fn ephemeral(mut self, policy: EphemeralPolicy) -> Self {
+        self.ephemeral = policy;
+        self
+    }
+
+    /// Specifies whether to exclude the ports reserved through `net.ipv4.ip_local_reserved_ports`, Default is `false`.
+    pub fn exclude_reserved(mut self, exclude: bool) -> Self {
+        self.exclude_reserved = exclude;
+        self
+    }
+
+    /// Specifies whether to exclude the ports documented in `/etc/services` for the protocol, Default is `false`.
+    pub fn exclude_services(mut self, exclude: bool) -> Self {
+        self.exclude_services = exclude;
+        self
+    }
+
+    /// Specifies whether ports below 1024 may be picked, Default is `false`.
+    /// On Linux picking fails with `Errors::PrivilegedPort` if the process cannot bind them,
+    /// i.e. it lacks `CAP_NET_BIND_SERVICE` and they are below `net.ipv4.ip_unprivileged_port_start`.
+    pub fn allow_privileged(mut self, allow: bool) -> Self {
+        self.privileged = allow;
+        self
+    }
+
+    /// Specifies how many ports `pick_async` probes concurrently, Default is `64`.
+    #[cfg(feature = "tokio")]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    fn acquire_lease(&self, port: u16) -> bool {
+        self.lease
+            .as_ref()
+            .is_none_or(|registry| registry.acquire(port))
+    }
+
+    fn release_leases(&self, ports: impl IntoIterator<Item = u16>) {
+        if let Some(registry) = &self.lease {
+            for port in ports {
+                let _ = registry.release(port);
             }
         }
-        Err(Errors::NoAvailablePort)
     }
 
-    fn get_port(&self, ip_addrs: HashSet<IpAddr>) -> Result<u16> {
-        for port in self.range.clone() {
-            if self.exclude.contains(&port) {
-                continue;
+    /// Returns whether a port is filtered out of the range, reading the system configuration once.
+    /// Built once per pick and passed down to everything filtering ports.
+    fn excluded(&self) -> Box<Excluded<'_>> {
+        let ephemeral = match self.ephemeral {
+            EphemeralPolicy::Ignore => None,
+            EphemeralPolicy::Avoid | EphemeralPolicy::Only => Some(sysctl::ephemeral_port_range()),
+        };
+        let mut system = HashSet::new();
+        if self.exclude_reserved {
+            system.extend(sysctl::reserved_ports());
+        }
+        if self.exclude_services {
+            system.extend(services::service_ports(&self.protocol));
+        }
+        Box::new(move |port| {
+            if self.exclude.contains(&port) || system.contains(&port) {
+                return true;
             }
-            if utils::is_free_in_hosts(port, &ip_addrs, &self.protocol) {
-                return Ok(port);
+            match (&ephemeral, self.ephemeral) {
+                (Some(range), EphemeralPolicy::Avoid) => range.contains(&port),
+                (Some(range), EphemeralPolicy::Only) => !range.contains(&port),
+                _ => false,
             }
+        })
+    }
+
+    /// Returns the seed of the next random pick and records it as the last seed used.
+    fn next_seed(&self) -> u64 {
+        let seed = self
+            .seed
+            .or_else(|| env::var(SEED_ENV).ok()?.trim().parse().ok())
+            .unwrap_or_else(|| rand::thread_rng().gen());
+        *self.last_seed.lock().unwrap_or_else(|err| err.into_inner()) = Some(seed);
+        #[cfg(feature = "tracing")]
+        tracing::info!(seed, "picking a random port, set {} to replay", SEED_ENV);
+        seed
+    }
+
+    /// Yields the preferred ports, then the ports of the set in the order of the strategy.
+    /// Skips excluded ports, ports of the strategy outside the set, and ports already yielded.
+    fn candidates<'a>(
+        &'a self,
+        excluded: &'a Excluded<'a>,
+    ) -> Box<dyn Iterator<Item = u16> + Send + 'a> {
+        let mut rng = PickRng {
+            picker: self,
+            seeded: None,
+        };
+        let ports = self.strategy.candidates(self.ports.clone(), &mut rng);
+        let preferred = self
+            .preferred
+            .iter()
+            .copied()
+            .filter(move |port| !excluded(*port));
+        Box::new(
+            preferred
+                .chain(self.filter_candidates(ports, excluded))
+                .filter(utils::first_seen()),
+        )
+    }
+
+    /// Skips the excluded ports and the ports outside the set.
+    fn filter_candidates<'a>(
+        &'a self,
+        ports: Box<dyn Iterator<Item = u16> + Send + 'a>,
+        excluded: &'a Excluded<'a>,
+    ) -> Box<dyn Iterator<Item = u16> + Send + 'a> {
+        Box::new(ports.filter(move |port| self.ports.contains(*port) && !excluded(*port)))
+    }
+
+    /// Walks the free candidate ports in order until `accept` returns a value.
+    fn find<T>(
+        &self,
+        prober: &Prober,
+        excluded: &Excluded,
+        accept: impl FnMut(u16) -> Option<T>,
+    ) -> Result<T> {
+        self.find_in(self.candidates(excluded), prober, excluded, accept)
+    }
+
+    /// Walks the free ports of `candidates` in order until `accept` returns a value.
+    /// The ports are probed by `threads` threads.
+    fn find_in<T>(
+        &self,
+        candidates: Box<dyn Iterator<Item = u16> + Send + '_>,
+        prober: &Prober,
+        excluded: &Excluded,
+        accept: impl FnMut(u16) -> Option<T>,
+    ) -> Result<T> {
+        let check = |port| prober.check(port);
+        self.find_checked(candidates, prober, excluded, check, accept)
+    }
+
+    /// Walks the candidates that `check` finds free in order until `accept` returns a value.
+    /// The candidates are checked by `threads` threads.
+    fn find_checked<T>(
+        &self,
+        candidates: Box<dyn Iterator<Item = u16> + Send + '_>,
+        prober: &Prober,
+        excluded: &Excluded,
+        check: impl Fn(u16) -> Option<ErrorKind> + Sync,
+        accept: impl FnMut(u16) -> Option<T>,
+    ) -> Result<T> {
+        utils::find_free(candidates, prober, self.threads, check, accept)
+            .map_err(|tally| self.exhausted(tally, excluded))
+    }
+
+    /// Describes why no port was found after checking the candidates.
+    fn exhausted(&self, tally: Tally, is_excluded: &Excluded) -> Errors {
+        if let Some((port, kind)) = tally.failed {
+            return Errors::ProbeFailed {
+                port,
+                outcome: kind.into(),
+            };
+        }
+        let excluded = self.ports.iter().filter(|port| is_excluded(*port)).count();
+        if tally.checked == 0 && excluded >= self.ports.len() {
+            return Errors::AllExcluded;
+        }
+        if tally.denied > 0 && tally.denied == tally.checked {
+            return Errors::PermissionDenied {
+                checked: tally.checked,
+            };
+        }
+        Errors::RangeExhausted {
+            checked: tally.checked,
+            excluded,
         }
-        Err(Errors::NoAvailablePort)
     }
 
-    pub fn pick(&self) -> Result<u16> {
-        // check params
-        if self.range.is_empty() {
+    fn check_options(&self) -> Result<()> {
+        if self.ports.is_empty() {
+            return Err(Errors::InvalidOption(
+                "The port range must not be empty, the start port must be less than or equal to the end port".to_string(),
+            ));
+        }
+        let min_port = if self.privileged { 1 } else { MIN_PORT };
+        let hint = if self.privileged {
+            ""
+        } else {
+            ", use allow_privileged to pick lower ports"
+        };
+        if self.ports.first().is_some_and(|port| port < min_port) {
+            return Err(Errors::InvalidOption(format!(
+                "The port range must be between {} and {}{}",
+                min_port, MAX_PORT, hint
+            )));
+        }
+        if let Some(port) = self.preferred.iter().find(|port| **port < min_port) {
+            return Err(Errors::InvalidOption(format!(
+                "The preferred port {} must be between {} and {}{}",
+                port, min_port, MAX_PORT, hint
+            )));
+        }
+        let lowest = self
+            .ports
+            .first()
+            .into_iter()
+            .chain(self.preferred.iter().copied())
+            .min();
+        if let Some(port) = lowest.filter(|port| *port < MIN_PORT) {
+            let bindable = sysctl::lowest_bindable_port();
+            if port < bindable {
+                return Err(Errors::PrivilegedPort {
+                    port,
+                    lowest: bindable,
+                });
+            }
+        }
+        if self.threads == 0 {
             return Err(Errors::InvalidOption(
-                "The start port must be less than or equal to the end port".to_string(),
+                "The number of threads must be greater than 0".to_string(),
             ));
         }
-        if *self.range.start() < MIN_PORT || *self.range.end() > MAX_PORT {
+        if let Some(registry) = &self.lease {
+            registry.prepare().map_err(Errors::LeaseRegistry)?;
+        }
+        Ok(())
+    }
+
+    fn ip_addrs(&self) -> Result<Vec<Host>> {
+        let mut resolved = Vec::new();
+        for host in &self.hosts {
+            for ip_addr in utils::resolve_host(host)? {
+                utils::push_host(&mut resolved, ip_addr);
+            }
+        }
+        self.select_hosts(resolved)
+    }
+
+    /// Filters the addresses the hosts resolved to by address family,
+    /// or uses the local addresses of the selected interfaces if no host is specified.
+    fn select_hosts(&self, mut ip_addrs: Vec<Host>) -> Result<Vec<Host>> {
+        if self.hosts.is_empty() {
+            ip_addrs = utils::get_local_hosts(&self.interfaces)?;
+        }
+        ip_addrs.retain(|host| self.family.contains(&host.ip));
+        if ip_addrs.is_empty() {
             return Err(Errors::InvalidOption(format!(
-                "The port range must be between {} and {}",
-                MIN_PORT, MAX_PORT
+                "No {:?} address to check",
+                self.family
             )));
         }
+        Ok(ip_addrs)
+    }
 
-        let mut ip_addrs: HashSet<IpAddr> = HashSet::new();
-        if let Some(host) = &self.host {
-            if let Ok(ip_addr) = host.parse::<IpAddr>() {
-                ip_addrs.insert(ip_addr);
+    /// The addresses to bind for picks that keep or request a socket, in the order the hosts were specified,
+    /// or the unspecified address of the address family if no host is specified.
+    /// An unspecified address covers the other addresses, so only the first one is bound if there is one.
+    fn bind_addrs(&self, prober: &Prober) -> Vec<Host> {
+        if self.hosts.is_empty() {
+            let ip: IpAddr = match self.family {
+                AddressFamily::Ipv6 => Ipv6Addr::UNSPECIFIED.into(),
+                AddressFamily::Any | AddressFamily::Ipv4 => Ipv4Addr::UNSPECIFIED.into(),
+            };
+            return vec![ip.into()];
+        }
+        match prober.hosts().iter().find(|host| host.ip.is_unspecified()) {
+            Some(host) => vec![*host],
+            None => prober.hosts().to_vec(),
+        }
+    }
+
+    /// Builds the prober of a pick, failing if a host cannot be probed and the policy does not count it as free,
+    /// or if no host can be probed.
+    fn prober(&self) -> Result<Prober> {
+        self.prober_for(self.ip_addrs()?)
+    }
+
+    fn prober_for(&self, hosts: Vec<Host>) -> Result<Prober> {
+        self.check_prober(Prober::new(
+            hosts,
+            self.protocol,
+            self.detection,
+            self.policies,
+        ))
+    }
+
+    /// Fails if a host of the prober cannot be probed and the policy does not count it as free,
+    /// or if no host can be probed.
+    fn check_prober(&self, prober: Prober) -> Result<Prober> {
+        if let Some(failure) = prober.unprobeable().first() {
+            let skip = self.policies.unprobeable == OutcomePolicy::Free;
+            if !skip || prober.hosts().is_empty() {
+                return Err(Errors::Unprobeable {
+                    host: failure.host,
+                    scope_id: failure.scope_id,
+                    kind: failure.kind,
+                });
+            }
+        }
+        Ok(prober)
+    }
+
+    pub fn pick(&self) -> Result<u16> {
+        self.pick_with_source().map(|picked| picked.port)
+    }
+
+    /// Picks a free port like `pick`, and tells whether it is the exact preferred port, a fallback or from the range.
+    pub fn pick_with_source(&self) -> Result<Picked> {
+        self.check_options()?;
+        let prober = self.prober()?;
+        let excluded = self.excluded();
+        self.find(&prober, &excluded, |port| {
+            if !self.acquire_lease(port) {
+                return None;
+            }
+            let source = match self
+                .preferred
+                .iter()
+                .position(|preferred| *preferred == port)
+            {
+                Some(0) => PickSource::Exact,
+                Some(index) => PickSource::Fallback(index),
+                None => PickSource::Range,
+            };
+            Some(Picked { port, source })
+        })
+    }
+
+    /// Picks a stable port for a key, such as a service or test name.
+    ///
+    /// The key is hashed into the port set and the ports are probed forward from there, wrapping around the set,
+    /// so the same key usually lands on the same port across runs while still avoiding busy ports.
+    /// The strategy is not used.
+    pub fn pick_for_key(&self, key: &str) -> Result<u16> {
+        self.check_options()?;
+        let prober = self.prober()?;
+        let len = self.ports.len();
+        let first = (utils::key_hash(key) % len as u64) as usize;
+        let ports = (first..len)
+            .chain(0..first)
+            .filter_map(|index| self.ports.get(index));
+        let excluded = self.excluded();
+        let candidates = self.filter_candidates(Box::new(ports), &excluded);
+        self.find_in(candidates, &prober, &excluded, |port| {
+            self.acquire_lease(port).then_some(port)
+        })
+    }
+
+    /// Picks a free port like `pick`, and reports every port tried on the way,
+    /// on which hosts it was not free and the error binding it failed with.
+    ///
+    /// Ports are probed sequentially on every host, so this is slower than `pick`.
+    pub fn pick_with_report(&self) -> PickReport {
+        let mut report = PickReport {
+            result: Err(Errors::AllExcluded),
+            hosts: Vec::new(),
+            unprobeable: Vec::new(),
+            attempts: Vec::new(),
+        };
+        let prober = match self.check_options().and_then(|_| self.prober()) {
+            Ok(prober) => prober,
+            Err(err) => {
+                report.result = Err(err);
+                return report;
+            }
+        };
+        report.hosts = prober
+            .hosts()
+            .iter()
+            .map(|host| host.socket_addr(0))
+            .collect();
+        report.unprobeable = prober.unprobeable().to_vec();
+        let excluded = self.excluded();
+        let mut tally = Tally::default();
+        for port in self.candidates(&excluded) {
+            let failures = prober.probe(port);
+            for failure in &failures {
+                utils::trace_failure(port, failure);
+            }
+            tally.record(failures.first().map(|failure| failure.kind));
+            if let Some(failure) = failures
+                .iter()
+                .find(|failure| self.policies.fails(failure.kind))
+            {
+                tally.fails(port, Some(failure.kind), &self.policies);
+            }
+            let free = failures.is_empty();
+            let leased = free && !self.acquire_lease(port);
+            report.attempts.push(Attempt {
+                port,
+                failures,
+                leased,
+            });
+            if tally.failed.is_some() {
+                break;
+            }
+            if free && !leased {
+                report.result = Ok(port);
+                return report;
+            }
+        }
+        report.result = Err(self.exhausted(tally, &excluded));
+        report
+    }
+
+    /// Picks `n` distinct free ports.
+    ///
+    /// Fails atomically with `Errors::NoAvailablePort` if the range does not contain `n` free ports,
+    /// no partial result is returned. Other failures, e.g. `Errors::PermissionDenied`, are reported as such.
+    pub fn pick_many(&self, n: usize) -> Result<Vec<u16>> {
+        self.check_options()?;
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        let prober = self.prober()?;
+        let excluded = self.excluded();
+        let mut ports: Vec<u16> = Vec::with_capacity(n.min(u16::MAX as usize));
+        let result = self.find(&prober, &excluded, |port| {
+            if ports.contains(&port) || !self.acquire_lease(port) {
+                return None;
+            }
+            ports.push(port);
+            (ports.len() == n).then_some(())
+        });
+        if let Err(err) = result {
+            self.release_leases(ports);
+            return Err(not_enough_ports(err));
+        }
+        Ok(ports)
+    }
+
+    /// Picks a block of `len` consecutive free ports, returned as an inclusive range.
+    ///
+    /// Every port of the block must be in the port set and not excluded. The first port is a multiple of `align`.
+    /// Fails with `Errors::NoAvailablePort` if the range does not contain such a block.
+    pub fn pick_block(&self, len: u16) -> Result<RangeInclusive<u16>> {
+        self.check_options()?;
+        if len == 0 {
+            return Err(Errors::InvalidOption(
+                "The block length must be greater than 0".to_string(),
+            ));
+        }
+        if self.align == 0 {
+            return Err(Errors::InvalidOption(
+                "The alignment must be greater than 0".to_string(),
+            ));
+        }
+        let prober = self.prober()?;
+        let excluded = self.excluded();
+        let starts = self.candidates(&excluded).filter(|start| {
+            start % self.align == 0
+                && start.checked_add(len - 1).is_some_and(|last| {
+                    (*start..=last).all(|port| self.ports.contains(port) && !excluded(port))
+                })
+        });
+        let check = |start| prober.check_block(start, len);
+        let result = self.find_checked(Box::new(starts), &prober, &excluded, check, |start| {
+            let block = start..=start + (len - 1);
+            for port in block.clone() {
+                if !self.acquire_lease(port) {
+                    self.release_leases(*block.start()..port);
+                    return None;
+                }
+            }
+            Some(block)
+        });
+        result.map_err(not_enough_ports)
+    }
+
+    /// Picks a free port without blocking the runtime.
+    ///
+    /// Ports are probed in batches of `concurrency`, with every host of a port probed concurrently.
+    /// The first free port of a batch in candidate order is returned, so sequential picking still returns the first available port.
+    #[cfg(feature = "tokio")]
+    pub async fn pick_async(&self) -> Result<u16> {
+        self.check_options()?;
+        if self.concurrency == 0 {
+            return Err(Errors::InvalidOption(
+                "The concurrency must be greater than 0".to_string(),
+            ));
+        }
+        let mut resolved = Vec::new();
+        for host in &self.hosts {
+            for ip_addr in async_utils::resolve_host(host).await? {
+                utils::push_host(&mut resolved, ip_addr);
+            }
+        }
+        // Binding port 0 on the hosts and reading the socket tables block, so the prober is built off the runtime
+        let hosts = self.select_hosts(resolved)?;
+        let (protocol, detection, policies) = (self.protocol, self.detection, self.policies);
+        let prober =
+            tokio::task::spawn_blocking(move || Prober::new(hosts, protocol, detection, policies))
+                .await
+                .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+        let prober = self.check_prober(prober)?;
+        let ip_addrs = std::sync::Arc::new(prober.hosts().to_vec());
+        let excluded = self.excluded();
+        let mut candidates = self.candidates(&excluded);
+        let mut tally = Tally::default();
+        loop {
+            let batch: Vec<u16> = candidates.by_ref().take(self.concurrency).collect();
+            if batch.is_empty() {
+                return Err(self.exhausted(tally, &excluded));
+            }
+            let mut failures = if prober.binds() {
+                async_utils::check_in_hosts(&batch, &ip_addrs, prober.protocol(), self.policies)
+                    .await
             } else {
-                return Err(Errors::InvalidOption(format!(
-                    "The host {} is not a valid IP address",
-                    host
-                )));
+                vec![None; batch.len()]
+            };
+            for (port, failure) in batch.iter().zip(failures.iter_mut()) {
+                if !prober.is_free_in_table(*port) {
+                    *failure = Some(ErrorKind::AddrInUse);
+                }
+            }
+            for (port, failure) in batch.into_iter().zip(failures) {
+                tally.record(failure);
+                if tally.fails(port, failure, &self.policies) {
+                    return Err(self.exhausted(tally, &excluded));
+                }
+                if failure.is_none() && self.acquire_lease(port) {
+                    return Ok(port);
+                }
             }
-        } else {
-            ip_addrs = utils::get_local_hosts();
         }
-        if self.random {
-            self.random_port(ip_addrs)
-        } else {
-            self.get_port(ip_addrs)
+    }
+
+    /// Lets the OS choose the port by binding port 0 on the first specified host, or on `0.0.0.0` (`::` for Ipv6 only) if no host is specified.
+    ///
+    /// The assigned port is then checked like any candidate: it must be in the port set, not excluded,
+    /// and free for every protocol of `Protocol::All` on every host. Otherwise another port is requested,
+    /// up to 32 times. Preferred ports and the strategy are not used.
+    ///
+    /// The OS assigns ports from the ephemeral port range, so the pick fails with `Errors::InvalidOption`
+    /// if no port of the set is in it once excluded, e.g. with `EphemeralPolicy::Avoid`.
+    /// If no port is found, `Errors::RangeExhausted` counts the ports assigned as checked,
+    /// and those outside the set or excluded as excluded.
+    pub fn pick_from_os(&self) -> Result<u16> {
+        self.check_options()?;
+        let excluded = self.excluded();
+        let ephemeral = sysctl::ephemeral_port_range();
+        if !ephemeral
+            .clone()
+            .any(|port| self.ports.contains(port) && !excluded(port))
+        {
+            return Err(Errors::InvalidOption(format!(
+                "No port of the set outside the exclusions is in the ephemeral port range {}-{}, which the OS assigns ports from",
+                ephemeral.start(),
+                ephemeral.end()
+            )));
+        }
+        let prober = self.prober()?;
+        let bind_addr = self.bind_addrs(&prober)[0];
+        let mut tally = Tally::default();
+        let mut skipped = 0;
+        for _ in 0..OS_ASSIGNED_ATTEMPTS {
+            let port = match utils::os_assigned_port(&bind_addr, &self.protocol) {
+                Ok(port) => port,
+                Err(err) => {
+                    tally.record(Some(err.kind()));
+                    continue;
+                }
+            };
+            if !self.ports.contains(port) || excluded(port) {
+                tally.checked += 1;
+                skipped += 1;
+                continue;
+            }
+            let failure = prober.check(port);
+            tally.record(failure);
+            if tally.fails(port, failure, &self.policies) {
+                break;
+            }
+            if failure.is_none() && self.acquire_lease(port) {
+                return Ok(port);
+            }
+        }
+        Err(match self.exhausted(tally, &excluded) {
+            Errors::RangeExhausted { checked, .. } => Errors::RangeExhausted {
+                checked,
+                excluded: skipped,
+            },
+            err => err,
+        })
+    }
+
+    /// Picks a free port and keeps it bound until the returned [`ReservedPort`] is taken, released or dropped.
+    ///
+    /// The sockets are bound on every specified host, or on `0.0.0.0` (`::` for Ipv6 only) if no host is specified.
+    /// If an unspecified address is among the hosts, it is bound instead of the others.
+    pub fn pick_reserved(&self) -> Result<ReservedPort> {
+        self.check_options()?;
+        let prober = self.prober()?;
+        let bind_addrs = self.bind_addrs(&prober);
+        let excluded = self.excluded();
+        self.find(&prober, &excluded, |port| {
+            let reserved = ReservedPort::bind(port, &bind_addrs, &self.protocol).ok()?;
+            self.acquire_lease(port).then_some(reserved)
+        })
+    }
+}
+
+/// `pick_many` and `pick_block` fail with `Errors::NoAvailablePort` when there are not enough free ports,
+/// other errors are kept.
+fn not_enough_ports(err: Errors) -> Errors {
+    match err {
+        Errors::RangeExhausted { .. } | Errors::AllExcluded => Errors::NoAvailablePort,
+        err => err,
+    }
+}
+
+/// Whether a port is filtered out of the range by the exclusions of a picker
+type Excluded<'a> = dyn Fn(u16) -> bool + Send + Sync + 'a;
+
+/// The random number generator of a pick, seeded on first use so that picks not using it record no seed
+struct PickRng<'a> {
+    picker: &'a PortPicker,
+    seeded: Option<StdRng>,
+}
+
+impl PickRng<'_> {
+    fn with<T>(&mut self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        if let Some(rng) = &self.picker.rng {
+            return f(&mut **rng.lock().unwrap_or_else(|err| err.into_inner()));
         }
+        let picker = self.picker;
+        f(self
+            .seeded
+            .get_or_insert_with(|| StdRng::seed_from_u64(picker.next_seed())))
+    }
+}
+
+impl RngCore for PickRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        self.with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        self.with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+impl Default for PortPicker {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 /// Check if a port is free in the local machine.
 /// If the host is not specified, it will check on all local addresses defined in the system.
+/// The port is not free if no port can be bound on one of the hosts, e.g. an address that is not available.
 ///
 /// - `port`: The port to check.
-/// - `host`: The host to check. Can be either an Ipv4 or Ipv6 address.
+/// - `host`: The host to check. Can be an Ipv4 or Ipv6 address, or a hostname checked on every address it resolves to.
 /// - `protocol`: The protocol to check. Can be either `Protocol::Tcp`, `Protocol::Udp` or `Protocol::All`.
 pub fn is_free(port: u16, host: Option<String>, protocol: Protocol) -> bool {
-    let mut ip_addrs: HashSet<IpAddr> = HashSet::new();
-    if let Some(host) = host {
-        if let Ok(ip_addr) = host.parse::<IpAddr>() {
-            ip_addrs.insert(ip_addr);
-        } else {
-            return false;
-        }
-    } else {
-        ip_addrs = utils::get_local_hosts();
+    let ip_addrs = match host {
+        Some(host) => utils::resolve_host(&host),
+        None => utils::get_local_hosts(&InterfaceFilter::default()),
+    };
+    let Ok(ip_addrs) = ip_addrs else {
+        return false;
+    };
+    // A host on which no port can be bound is not free, as when picking
+    let prober = Prober::new(
+        ip_addrs,
+        protocol,
+        Detection::Bind,
+        OutcomePolicies::default(),
+    );
+    prober.unprobeable().is_empty() && prober.check(port).is_none()
+}
+
+/// Bind a port on an address and tell what it found, without applying any policy.
+/// With `Protocol::All` the outcome of TCP is returned unless the port is free for TCP.
+///
+/// Ipv6 link-local addresses need their scope id, e.g. `"[fe80::1%2]:8080".parse()`.
+pub fn probe(addr: SocketAddr, protocol: Protocol) -> ProbeOutcome {
+    utils::probe_outcome(addr, &protocol)
+}
+
+/// Describe the sockets using a port in the local machine, and the processes holding them.
+///
+/// Reads the kernel's socket tables, so it is only supported on Linux.
+/// The owning process is only found if its descriptors are readable by the current user.
+pub fn port_info(port: u16) -> Result<Vec<PortInfo>> {
+    info::port_info(port)
+}
+
+/// Check if a port is free in the local machine without blocking the runtime.
+/// If the host is not specified, it will check on all local addresses defined in the system.
+/// The port is not free if no port can be bound on one of the hosts, e.g. an address that is not available.
+///
+/// - `port`: The port to check.
+/// - `host`: The host to check. Can be an Ipv4 or Ipv6 address, or a hostname checked on every address it resolves to.
+/// - `protocol`: The protocol to check. Can be either `Protocol::Tcp`, `Protocol::Udp` or `Protocol::All`.
+#[cfg(feature = "tokio")]
+pub async fn is_free_async(port: u16, host: Option<String>, protocol: Protocol) -> bool {
+    let ip_addrs = match host {
+        Some(host) => async_utils::resolve_host(&host).await.ok(),
+        None => utils::get_local_hosts(&InterfaceFilter::default()).ok(),
+    };
+    let Some(ip_addrs) = ip_addrs else {
+        return false;
+    };
+    if async_utils::any_unprobeable(&ip_addrs, protocol).await {
+        return false;
     }
-    utils::is_free_in_hosts(port, &ip_addrs, &protocol)
+    let ip_addrs = std::sync::Arc::new(ip_addrs);
+    async_utils::is_free_in_hosts(port, ip_addrs, protocol, OutcomePolicies::default()).await
 }
 
 #[cfg(test)]
@@ -173,12 +1067,12 @@ mod tests {
     #[test]
     fn test_port_picker() {
         let port = PortPicker::new().pick().unwrap();
-        assert!(port >= MIN_PORT && port <= MAX_PORT);
+        assert!(port >= MIN_PORT);
 
         let result = PortPicker::new().port_range(3000..=4000).pick();
         assert!(result.is_ok());
         let port = result.unwrap();
-        assert!(port >= 3000 && port <= 4000);
+        assert!((3000..=4000).contains(&port));
     }
 
     #[test]
@@ -188,4 +1082,571 @@ mod tests {
         // In my macos, port 80 is not free
         assert!(!is_free(80, None, Protocol::All));
     }
+
+    #[test]
+    fn test_is_free_on_unprobeable_host() {
+        let port = PortPicker::new().pick().unwrap();
+        assert!(is_free(port, Some("127.0.0.1".to_string()), Protocol::All));
+        assert!(!is_free(port, Some("192.0.2.1".to_string()), Protocol::All));
+        assert!(!is_free(port, Some("fe80::1".to_string()), Protocol::Tcp));
+    }
+
+    #[test]
+    fn test_pick_with_threads() {
+        let sequential = PortPicker::new().port_range(5000..=6000).threads(1);
+        let parallel = PortPicker::new().port_range(5000..=6000).threads(8);
+        assert_eq!(sequential.pick().unwrap(), parallel.pick().unwrap());
+
+        let result = PortPicker::new().threads(0).pick();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+
+        let picker = PortPicker::new()
+            .port_range(5000..=5003)
+            .prefer([5002, 5000, 5002]);
+        let candidates: Vec<u16> = picker.candidates(&picker.excluded()).collect();
+        assert_eq!(candidates, [5002, 5000, 5001, 5003]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_pick_with_procfs() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        for detection in [Detection::Procfs, Detection::Both] {
+            let result = PortPicker::new()
+                .port_range(port..=port)
+                .host("127.0.0.1".to_string())
+                .protocol(Protocol::Tcp)
+                .detection(detection)
+                .pick();
+            assert!(matches!(
+                result,
+                Err(Errors::RangeExhausted {
+                    checked: 1,
+                    excluded: 0
+                })
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_port_info() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let infos = port_info(port).unwrap();
+        let info = infos
+            .iter()
+            .find(|info| info.state == SocketState::Listen)
+            .unwrap();
+        assert_eq!(info.protocol, Protocol::Tcp);
+        assert_eq!(info.local, listener.local_addr().unwrap());
+        assert_eq!(info.pid, Some(std::process::id()));
+        assert!(info.to_string().contains("held by"));
+    }
+
+    #[test]
+    fn test_pick_with_report() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let report = PortPicker::new()
+            .port_range(port..=port.saturating_add(1))
+            .host("127.0.0.1".to_string())
+            .protocol(Protocol::Tcp)
+            .pick_with_report();
+        assert_eq!(report.hosts, vec!["127.0.0.1:0".parse().unwrap()]);
+        let attempt = &report.attempts[0];
+        assert_eq!(attempt.port, port);
+        assert!(!attempt.is_free());
+        assert_eq!(attempt.failures[0].kind, std::io::ErrorKind::AddrInUse);
+        if let Ok(picked) = report.result {
+            assert_eq!(picked, port + 1);
+        }
+    }
+
+    #[test]
+    fn test_pick_errors() {
+        let result = PortPicker::new()
+            .port_range(3000..=3001)
+            .execlude([3000, 3001].into())
+            .pick();
+        assert!(matches!(result, Err(Errors::AllExcluded)));
+
+        let result = PortPicker::new().host("not a host".to_string()).pick();
+        assert!(matches!(result, Err(Errors::HostResolution { .. })));
+
+        let port = PortPicker::new()
+            .host("localhost".to_string())
+            .pick()
+            .unwrap();
+        assert!(is_free(port, Some("localhost".to_string()), Protocol::All));
+    }
+
+    #[test]
+    fn test_pick_with_ephemeral_policy() {
+        let ephemeral = sysctl::ephemeral_port_range();
+        let port = PortPicker::new()
+            .ephemeral(EphemeralPolicy::Only)
+            .pick()
+            .unwrap();
+        assert!(ephemeral.contains(&port));
+
+        let result = PortPicker::new()
+            .port_range(ephemeral.clone())
+            .ephemeral(EphemeralPolicy::Avoid)
+            .pick();
+        assert!(matches!(result, Err(Errors::AllExcluded)));
+    }
+
+    #[test]
+    fn test_pick_excluding_services() {
+        let services = services::service_ports(&Protocol::Tcp);
+        let port = PortPicker::new()
+            .protocol(Protocol::Tcp)
+            .exclude_reserved(true)
+            .exclude_services(true)
+            .pick()
+            .unwrap();
+        assert!(!services.contains(&port));
+        assert!(!sysctl::reserved_ports().contains(&port));
+    }
+
+    #[test]
+    fn test_pick_random() {
+        // Every port is tried, so the only free port of the block is always found
+        let block = PortPicker::new()
+            .port_range(6000..=7000)
+            .pick_block(20)
+            .unwrap();
+        let listeners: Vec<_> = block
+            .clone()
+            .skip(1)
+            .map(|port| std::net::TcpListener::bind(("0.0.0.0", port)).unwrap())
+            .collect();
+        let port = PortPicker::new()
+            .port_range(block.clone())
+            .protocol(Protocol::Tcp)
+            .random(true)
+            .pick()
+            .unwrap();
+        assert_eq!(port, *block.start());
+        drop(listeners);
+    }
+
+    #[test]
+    fn test_pick_with_seed() {
+        let picker = PortPicker::new()
+            .port_range(6000..=7000)
+            .random(true)
+            .seed(42);
+        let seeded: Vec<u16> = picker.candidates(&picker.excluded()).take(20).collect();
+        assert_eq!(picker.last_seed(), Some(42));
+        let replayed = PortPicker::new()
+            .port_range(6000..=7000)
+            .random(true)
+            .seed(42);
+        assert_eq!(
+            replayed
+                .candidates(&replayed.excluded())
+                .take(20)
+                .collect::<Vec<_>>(),
+            seeded
+        );
+
+        let picker = PortPicker::new()
+            .port_range(6000..=7000)
+            .random(true)
+            .rng(StdRng::seed_from_u64(7));
+        let custom: Vec<u16> = picker.candidates(&picker.excluded()).take(20).collect();
+        let mut rng = StdRng::seed_from_u64(7);
+        let expected: Vec<u16> = Random
+            .candidates(PortSet::from(6000..=7000), &mut rng)
+            .take(20)
+            .collect();
+        assert_eq!(custom, expected);
+        assert_eq!(picker.last_seed(), None);
+    }
+
+    #[test]
+    fn test_pick_with_strategy() {
+        let port = PortPicker::new()
+            .port_range(6000..=7000)
+            .strategy(Descending)
+            .pick()
+            .unwrap();
+        assert!(port > 6500);
+        let picker = PortPicker::new()
+            .port_range(6000..=7000)
+            .strategy(ClosestTo(6500));
+        assert_eq!(picker.candidates(&picker.excluded()).next(), Some(6500));
+        assert_eq!(picker.last_seed(), None);
+    }
+
+    #[test]
+    fn test_pick_for_key() {
+        let picker = PortPicker::new()
+            .port_range(7000..=8000)
+            .protocol(Protocol::Tcp);
+        let port = picker.pick_for_key("postgres").unwrap();
+        assert!((7000..=8000).contains(&port));
+        assert_eq!(picker.pick_for_key("postgres").unwrap(), port);
+
+        // A busy port moves the key to the next free port
+        let _listener = std::net::TcpListener::bind(("0.0.0.0", port)).unwrap();
+        let next = picker.pick_for_key("postgres").unwrap();
+        assert_ne!(next, port);
+    }
+
+    #[test]
+    fn test_pick_with_source() {
+        let block = PortPicker::new()
+            .port_range(8000..=9000)
+            .pick_block(3)
+            .unwrap();
+        let (first, second) = (*block.start(), block.start() + 1);
+        let picker = PortPicker::new()
+            .port_range(block.clone())
+            .protocol(Protocol::Tcp)
+            .prefer([first, second]);
+        let picked = picker.pick_with_source().unwrap();
+        assert_eq!(
+            picked,
+            Picked {
+                port: first,
+                source: PickSource::Exact
+            }
+        );
+
+        let _first = std::net::TcpListener::bind(("0.0.0.0", first)).unwrap();
+        let picked = picker.pick_with_source().unwrap();
+        assert_eq!(picked.source, PickSource::Fallback(1));
+
+        let _second = std::net::TcpListener::bind(("0.0.0.0", second)).unwrap();
+        let picked = picker.pick_with_source().unwrap();
+        assert_eq!(
+            picked,
+            Picked {
+                port: first + 2,
+                source: PickSource::Range
+            }
+        );
+
+        // Preferred ports outside the range count towards pick_many
+        let preferred = PortPicker::new().port_range(45000..=46000).pick().unwrap();
+        let ports = PortPicker::new()
+            .port_range(40000..=40001)
+            .prefer([preferred])
+            .pick_many(3)
+            .unwrap();
+        assert_eq!(ports, [preferred, 40000, 40001]);
+
+        // A busy preferred port is checked even if the whole range is excluded
+        let result = PortPicker::new()
+            .port_range(40000..=40000)
+            .execlude_add(40000)
+            .protocol(Protocol::Tcp)
+            .prefer([first])
+            .pick();
+        assert!(matches!(
+            result,
+            Err(Errors::RangeExhausted { checked: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_pick_from_port_set() {
+        let ports: PortSet = "20000-20009,30000-30009,!20000-20009".parse().unwrap();
+        let port = PortPicker::new().port_set(ports.clone()).pick().unwrap();
+        assert!((30000..=30009).contains(&port));
+        let port = PortPicker::new()
+            .port_set(ports)
+            .random(true)
+            .pick()
+            .unwrap();
+        assert!((30000..=30009).contains(&port));
+
+        let result = PortPicker::new().port_set(PortSet::new()).pick();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_pick_privileged() {
+        let result = PortPicker::new().port_range(1000..=1023).pick();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+
+        let result = PortPicker::new()
+            .port_range(1000..=1023)
+            .allow_privileged(true)
+            .pick();
+        if sysctl::lowest_bindable_port() <= 1000 {
+            assert!((1000..=1023).contains(&result.unwrap()));
+        } else {
+            assert!(matches!(
+                result,
+                Err(Errors::PrivilegedPort { port: 1000, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_pick_from_os() {
+        let port = PortPicker::new()
+            .host("127.0.0.1".to_string())
+            .pick_from_os()
+            .unwrap();
+        assert!(is_free(port, None, Protocol::All));
+
+        let port = PortPicker::new()
+            .protocol(Protocol::Udp)
+            .pick_from_os()
+            .unwrap();
+        assert!(port >= MIN_PORT);
+
+        // The OS only assigns ports of the ephemeral range
+        let ephemeral = sysctl::ephemeral_port_range();
+        let result = PortPicker::new().port_range(2000..=2001).pick_from_os();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+        let result = PortPicker::new()
+            .ephemeral(EphemeralPolicy::Avoid)
+            .pick_from_os();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+
+        // The ports assigned outside the set are counted
+        let start = *ephemeral.start();
+        let result = PortPicker::new().port_range(start..=start).pick_from_os();
+        assert!(
+            matches!(
+                result,
+                Ok(port) if port == start
+            ) || matches!(
+                result,
+                Err(Errors::RangeExhausted { checked, excluded }) if checked == OS_ASSIGNED_ATTEMPTS && excluded > 0
+            )
+        );
+    }
+
+    fn ips(picker: &PortPicker) -> HashSet<IpAddr> {
+        let hosts = picker.ip_addrs().unwrap();
+        hosts.iter().map(|host| host.ip).collect()
+    }
+
+    #[test]
+    fn test_pick_on_interfaces() {
+        let picker = PortPicker::new().interfaces(["lo*"]);
+        assert!(ips(&picker).iter().all(IpAddr::is_loopback));
+        assert!(picker.pick().is_ok());
+
+        let result = PortPicker::new()
+            .interfaces(["lo*"])
+            .exclude_interfaces(["lo*"])
+            .pick();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_pick_on_hosts() {
+        let picker = PortPicker::new().hosts(["127.0.0.1", "::1"]);
+        let expected: HashSet<IpAddr> =
+            HashSet::from([Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()]);
+        assert_eq!(ips(&picker), expected);
+
+        let picker = picker.address_family(AddressFamily::Ipv6);
+        let expected: HashSet<IpAddr> = HashSet::from([Ipv6Addr::LOCALHOST.into()]);
+        assert_eq!(ips(&picker), expected);
+
+        let picker = PortPicker::new().address_family(AddressFamily::Ipv4);
+        assert!(ips(&picker).iter().all(IpAddr::is_ipv4));
+        assert!(picker.pick().is_ok());
+
+        let result = PortPicker::new()
+            .host("127.0.0.1".to_string())
+            .address_family(AddressFamily::Ipv6)
+            .pick();
+        assert!(matches!(result, Err(Errors::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_pick_with_unprobeable_hosts() {
+        let unavailable: IpAddr = "192.0.2.1".parse().unwrap();
+        let picker = PortPicker::new().hosts(["127.0.0.1", "192.0.2.1"]);
+        let report = picker.pick_with_report();
+        assert!(report.result.is_ok());
+        assert_eq!(report.hosts, vec!["127.0.0.1:0".parse().unwrap()]);
+        assert_eq!(report.unprobeable[0].host, unavailable);
+        assert_eq!(report.unprobeable[0].kind, ErrorKind::AddrNotAvailable);
+
+        // A link-local host is reported with its scope
+        let scoped = utils::get_local_hosts(&InterfaceFilter::default())
+            .unwrap()
+            .into_iter()
+            .find(|host| host.scope_id != 0);
+        if let Some(scoped) = scoped {
+            let report = PortPicker::new()
+                .host(scoped.to_string())
+                .pick_with_report();
+            assert!(report.result.is_ok());
+            assert_eq!(report.hosts, vec![scoped.socket_addr(0)]);
+        }
+
+        let result = picker.unprobeable_ports(OutcomePolicy::Fail).pick();
+        assert!(matches!(result, Err(Errors::Unprobeable { host, .. }) if host == unavailable));
+
+        let result = PortPicker::new().host("192.0.2.1".to_string()).pick();
+        assert!(matches!(result, Err(Errors::Unprobeable { .. })));
+    }
+
+    #[test]
+    fn test_probe() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(probe(addr, Protocol::Tcp), ProbeOutcome::InUse);
+        assert!(probe(addr, Protocol::Udp).is_free());
+
+        let unavailable = "192.0.2.1:8080".parse().unwrap();
+        assert_eq!(
+            probe(unavailable, Protocol::Tcp),
+            ProbeOutcome::Unprobeable(ErrorKind::AddrNotAvailable)
+        );
+        assert_eq!(
+            ProbeOutcome::from(ErrorKind::PermissionDenied),
+            ProbeOutcome::Denied
+        );
+    }
+
+    #[test]
+    fn test_pick_with_outcome_policy() {
+        // A host that cannot be probed is left out if it counts as free, and fails the pick otherwise
+        let picker = || PortPicker::new().hosts(["127.0.0.1", "192.0.2.1"]);
+        let report = picker().pick_with_report();
+        assert!(report.result.is_ok());
+        assert_eq!(
+            report.unprobeable[0].outcome(),
+            ProbeOutcome::Unprobeable(ErrorKind::AddrNotAvailable)
+        );
+        for policy in [OutcomePolicy::InUse, OutcomePolicy::Fail] {
+            let report = picker().unprobeable_ports(policy).pick_with_report();
+            assert!(matches!(
+                report.result,
+                Err(Errors::Unprobeable {
+                    kind: ErrorKind::AddrNotAvailable,
+                    ..
+                })
+            ));
+        }
+
+        // A port in use is not denied, so the denied policy does not fail the pick
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let picker = PortPicker::new()
+            .host("127.0.0.1".to_string())
+            .port_range(port..=port)
+            .denied_ports(OutcomePolicy::Fail);
+        assert!(matches!(picker.pick(), Err(Errors::RangeExhausted { .. })));
+    }
+
+    #[test]
+    fn test_pick_many() {
+        let ports = PortPicker::new()
+            .port_range(3000..=4000)
+            .random(true)
+            .pick_many(5)
+            .unwrap();
+        assert_eq!(ports.len(), 5);
+        let unique: HashSet<u16> = ports.iter().copied().collect();
+        assert_eq!(unique.len(), 5);
+        assert!(ports.iter().all(|port| (3000..=4000).contains(port)));
+
+        let result = PortPicker::new().port_range(3000..=3001).pick_many(3);
+        assert!(matches!(result, Err(Errors::NoAvailablePort)));
+        let result = PortPicker::new()
+            .port_range(3000..=3002)
+            .execlude_add(3001)
+            .pick_many(3);
+        assert!(matches!(result, Err(Errors::NoAvailablePort)));
+    }
+
+    #[test]
+    fn test_pick_block() {
+        let block = PortPicker::new()
+            .port_range(3000..=4000)
+            .align(10)
+            .pick_block(4)
+            .unwrap();
+        assert_eq!(block.clone().count(), 4);
+        assert_eq!(block.start() % 10, 0);
+        assert!(block.clone().all(|port| (3000..=4000).contains(&port)));
+
+        let block = PortPicker::new()
+            .port_range(3000..=3010)
+            .execlude_add(3001)
+            .pick_block(3)
+            .unwrap();
+        assert!(!block.contains(&3001));
+
+        let result = PortPicker::new().port_range(3000..=3001).pick_block(3);
+        assert!(matches!(result, Err(Errors::NoAvailablePort)));
+        let result = PortPicker::new()
+            .port_range(3000..=3003)
+            .execlude_add(3001)
+            .pick_block(3);
+        assert!(matches!(result, Err(Errors::NoAvailablePort)));
+
+        let sequential = PortPicker::new().port_range(4000..=4100).threads(1);
+        let parallel = PortPicker::new().port_range(4000..=4100).threads(8);
+        assert_eq!(
+            sequential.pick_block(8).unwrap(),
+            parallel.pick_block(8).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pick_with_lease_registry() {
+        let dir = std::env::temp_dir().join(format!("random-port-picker-{}", std::process::id()));
+        let picker = PortPicker::new()
+            .port_range(3000..=4000)
+            .lease_registry(LeaseRegistry::new(&dir));
+        let first = picker.pick().unwrap();
+        let second = picker.pick().unwrap();
+        assert_ne!(first, second);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pick_reserved() {
+        let reserved = PortPicker::new().pick_reserved().unwrap();
+        let port = reserved.port();
+        assert!(!is_free(port, None, Protocol::Tcp));
+        assert!(!is_free(port, None, Protocol::Udp));
+        assert_eq!(reserved.release(), port);
+        assert!(is_free(port, None, Protocol::All));
+
+        let reserved = PortPicker::new()
+            .protocol(Protocol::Tcp)
+            .pick_reserved()
+            .unwrap();
+        let listener = reserved.into_tcp_listener();
+        assert!(listener.is_some());
+
+        // The port is held on every host, in the order the hosts were given
+        let reserved = PortPicker::new()
+            .hosts(["::1".to_string(), "127.0.0.1".to_string()])
+            .pick_reserved()
+            .unwrap();
+        let port = reserved.port();
+        assert!(!is_free(port, Some("127.0.0.1".to_string()), Protocol::Tcp));
+        assert!(!is_free(port, Some("::1".to_string()), Protocol::Udp));
+        let (tcp, udp) = reserved.into_all_sockets();
+        let ips: Vec<IpAddr> = tcp
+            .iter()
+            .map(|listener| listener.local_addr().unwrap().ip())
+            .collect();
+        assert_eq!(
+            ips,
+            [
+                IpAddr::from(Ipv6Addr::LOCALHOST),
+                Ipv4Addr::LOCALHOST.into()
+            ]
+        );
+        assert_eq!(udp.len(), 2);
+    }
 }
@@ -0,0 +1,81 @@
+use rand::Rng;
+
+const ROUNDS: usize = 4;
+
+/// A random permutation of `0..len`, walked without allocating it.
+///
+/// A Feistel network gives a keyed bijection over the smallest domain of `2^(2 * half_bits)` values
+/// holding `len`; values outside `0..len` are skipped, so every value is yielded exactly once.
+pub(crate) struct Permutation {
+    len: u64,
+    half_bits: u32,
+    keys: [u64; ROUNDS],
+    index: u64,
+}
+
+impl Permutation {
+    pub(crate) fn new<R: Rng + ?Sized>(len: u64, rng: &mut R) -> Self {
+        let bits = 64 - len.saturating_sub(1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+        Permutation {
+            len,
+            half_bits,
+            keys: rng.gen(),
+            index: 0,
+        }
+    }
+
+    fn domain(&self) -> u64 {
+        1 << (2 * self.half_bits)
+    }
+
+    fn permute(&self, value: u64) -> u64 {
+        let mask = (1 << self.half_bits) - 1;
+        let (mut left, mut right) = (value >> self.half_bits, value & mask);
+        for key in self.keys {
+            let next = left ^ (mix(right ^ key) & mask);
+            left = right;
+            right = next;
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+impl Iterator for Permutation {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.index < self.domain() {
+            let value = self.permute(self.index);
+            self.index += 1;
+            if value < self.len {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// The SplitMix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation() {
+        let mut rng = rand::thread_rng();
+        for len in [0, 1, 2, 3, 1000, 64512, 65536] {
+            let mut values: Vec<u64> = Permutation::new(len, &mut rng).collect();
+            values.sort_unstable();
+            assert_eq!(values, (0..len).collect::<Vec<_>>());
+        }
+        let first: Vec<u64> = Permutation::new(1000, &mut rng).take(10).collect();
+        assert_ne!(first, (0..10).collect::<Vec<_>>());
+    }
+}
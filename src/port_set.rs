@@ -0,0 +1,236 @@
+use std::{fmt, iter::Flatten, ops::RangeInclusive, str::FromStr, vec};
+
+use crate::error::Errors;
+
+/// A set of ports made of unions of ranges, minus excluded ranges.
+///
+/// The ranges are kept sorted and merged, so the ports are iterated in ascending order.
+///
+/// #Examples:
+///
+/// ```
+/// use random_port::PortSet;
+/// let ports: PortSet = "20000-29999,40000-44999,!25000-25999".parse().unwrap();
+/// assert!(ports.contains(20000));
+/// assert!(!ports.contains(25000));
+/// assert_eq!(ports.len(), 14000);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortSet {
+    ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl PortSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the ports of `range` to the set.
+    pub fn with_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.insert(range);
+        self
+    }
+
+    /// Removes the ports of `range` from the set.
+    pub fn without_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.remove(range);
+        self
+    }
+
+    /// Adds the ports of `range` to the set.
+    pub fn insert(&mut self, range: RangeInclusive<u16>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (*range.start(), *range.end());
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for existing in self.ranges.drain(..) {
+            // Disjoint and not adjacent ranges are kept, the others are merged into the new one
+            if (*existing.end() as u32) + 1 < start as u32
+                || (end as u32) + 1 < *existing.start() as u32
+            {
+                ranges.push(existing);
+            } else {
+                start = start.min(*existing.start());
+                end = end.max(*existing.end());
+            }
+        }
+        ranges.push(start..=end);
+        ranges.sort_by_key(|range| *range.start());
+        self.ranges = ranges;
+    }
+
+    /// Removes the ports of `range` from the set.
+    pub fn remove(&mut self, range: RangeInclusive<u16>) {
+        if range.is_empty() {
+            return;
+        }
+        let (start, end) = (*range.start(), *range.end());
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for existing in self.ranges.drain(..) {
+            if *existing.end() < start || *existing.start() > end {
+                ranges.push(existing);
+                continue;
+            }
+            if *existing.start() < start {
+                ranges.push(*existing.start()..=start - 1);
+            }
+            if *existing.end() > end {
+                ranges.push(end + 1..=*existing.end());
+            }
+        }
+        self.ranges = ranges;
+    }
+
+    /// Returns whether the set holds `port`.
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&port))
+    }
+
+    /// Returns the number of ports in the set.
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|range| range.len()).sum()
+    }
+
+    /// Returns whether the set holds no port.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the sorted, disjoint ranges of the set.
+    pub fn ranges(&self) -> &[RangeInclusive<u16>] {
+        &self.ranges
+    }
+
+    /// Returns the lowest port of the set.
+    pub fn first(&self) -> Option<u16> {
+        self.ranges.first().map(|range| *range.start())
+    }
+
+    /// Returns the highest port of the set.
+    pub fn last(&self) -> Option<u16> {
+        self.ranges.last().map(|range| *range.end())
+    }
+
+    /// Returns the `index`-th lowest port of the set.
+    pub fn get(&self, mut index: usize) -> Option<u16> {
+        for range in &self.ranges {
+            if index < range.len() {
+                return Some(range.start() + index as u16);
+            }
+            index -= range.len();
+        }
+        None
+    }
+
+    /// Returns the number of ports of the set lower than `port`.
+    pub(crate) fn rank(&self, port: u32) -> usize {
+        self.ranges
+            .iter()
+            .map(|range| {
+                let (start, end) = (*range.start() as u32, *range.end() as u32);
+                (port.clamp(start, end + 1) - start) as usize
+            })
+            .sum()
+    }
+
+    /// Iterates over the ports of the set in ascending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        self.ranges.iter().cloned().flatten()
+    }
+}
+
+impl From<RangeInclusive<u16>> for PortSet {
+    fn from(range: RangeInclusive<u16>) -> Self {
+        PortSet::new().with_range(range)
+    }
+}
+
+impl IntoIterator for PortSet {
+    type Item = u16;
+    type IntoIter = Flatten<vec::IntoIter<RangeInclusive<u16>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter().flatten()
+    }
+}
+
+impl FromStr for PortSet {
+    type Err = Errors;
+
+    /// Parses comma separated ports and ranges, where a leading `!` excludes them, e.g. `20000-29999,!25000`.
+    /// Exclusions apply to the whole set, whatever their position.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = PortSet::new();
+        let mut excluded = Vec::new();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (exclude, range) = match item.strip_prefix('!') {
+                Some(range) => (true, range.trim()),
+                None => (false, item),
+            };
+            let range = parse_range(range).ok_or_else(|| {
+                Errors::InvalidOption(format!("The port set item {} is not valid", item))
+            })?;
+            if exclude {
+                excluded.push(range);
+            } else {
+                set.insert(range);
+            }
+        }
+        for range in excluded {
+            set.remove(range);
+        }
+        Ok(set)
+    }
+}
+
+fn parse_range(range: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    Some(start..=end).filter(|range: &RangeInclusive<u16>| !range.is_empty())
+}
+
+impl fmt::Display for PortSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, range) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if range.start() == range.end() {
+                write!(f, "{}", range.start())?;
+            } else {
+                write!(f, "{}-{}", range.start(), range.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_set() {
+        let set: PortSet = "20000-29999, 40000-44999, !25000-25999".parse().unwrap();
+        assert_eq!(set.ranges(), [20000..=24999, 26000..=29999, 40000..=44999]);
+        assert_eq!(set.len(), 14000);
+        assert_eq!(set.get(5000), Some(26000));
+        assert_eq!(set.get(14000), None);
+        assert_eq!(set.rank(26000), 5000);
+        assert_eq!(set.iter().next_back(), Some(44999));
+        assert_eq!(set.to_string(), "20000-24999,26000-29999,40000-44999");
+
+        let set = PortSet::from(3000..=3001)
+            .with_range(3002..=3002)
+            .with_range(2990..=2999)
+            .without_range(2995..=2995);
+        assert_eq!(set.ranges(), [2990..=2994, 2996..=3002]);
+        assert_eq!(set.to_string().parse::<PortSet>().unwrap(), set);
+
+        assert!("!3000".parse::<PortSet>().unwrap().is_empty());
+        assert!("3000-2000".parse::<PortSet>().is_err());
+        assert!("http".parse::<PortSet>().is_err());
+    }
+}
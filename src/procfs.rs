@@ -0,0 +1,197 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use crate::{utils::Host, Protocol};
+
+const TABLES: [(&str, Protocol); 4] = [
+    ("/proc/net/tcp", Protocol::Tcp),
+    ("/proc/net/tcp6", Protocol::Tcp),
+    ("/proc/net/udp", Protocol::Udp),
+    ("/proc/net/udp6", Protocol::Udp),
+];
+
+/// A socket listed in the kernel's socket tables
+#[derive(Debug, Clone)]
+pub(crate) struct SocketEntry {
+    pub(crate) protocol: Protocol,
+    pub(crate) local: SocketAddr,
+    pub(crate) remote: SocketAddr,
+    pub(crate) state: u8,
+    pub(crate) inode: u64,
+}
+
+/// A snapshot of the sockets listed in `/proc/net/{tcp,tcp6,udp,udp6}`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SocketTable {
+    entries: Vec<SocketEntry>,
+}
+
+impl SocketTable {
+    /// Read the socket tables of the current network namespace.
+    /// A missing IPv6 table is skipped, as it is absent when IPv6 is disabled.
+    pub(crate) fn read() -> io::Result<Self> {
+        let mut table = SocketTable::default();
+        for (path, protocol) in TABLES {
+            match fs::read_to_string(path) {
+                Ok(content) => table.entries.extend(parse(&content, protocol)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound && path.ends_with('6') => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(table)
+    }
+
+    /// Sockets using `port` locally
+    pub(crate) fn entries_for(&self, port: u16) -> impl Iterator<Item = &SocketEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.local.port() == port)
+    }
+
+    /// Check if a port is free in all hosts.
+    /// Every listed socket counts as using its port, including connections in `TIME_WAIT`.
+    pub(crate) fn is_free_in_hosts(&self, port: u16, hosts: &[Host], protocol: &Protocol) -> bool {
+        hosts
+            .iter()
+            .all(|host| self.is_free(port, &host.ip, protocol))
+    }
+
+    /// Check if a port is free in a host
+    pub(crate) fn is_free(&self, port: u16, host: &IpAddr, protocol: &Protocol) -> bool {
+        !self.entries.iter().any(|entry| {
+            entry.local.port() == port
+                && matches_protocol(entry.protocol, protocol)
+                && conflicts(entry.local.ip(), *host)
+        })
+    }
+}
+
+fn matches_protocol(entry: Protocol, protocol: &Protocol) -> bool {
+    *protocol == Protocol::All || entry == *protocol
+}
+
+/// Check if binding `host` would conflict with a socket bound on `local`
+fn conflicts(local: IpAddr, host: IpAddr) -> bool {
+    let (local, host) = (local.to_canonical(), host.to_canonical());
+    if local == host {
+        return true;
+    }
+    match (local, host) {
+        (IpAddr::V6(local), _) if local.is_unspecified() => true,
+        (_, IpAddr::V6(host)) if host.is_unspecified() => true,
+        (IpAddr::V4(local), IpAddr::V4(host)) => local.is_unspecified() || host.is_unspecified(),
+        _ => false,
+    }
+}
+
+/// Find the processes owning the sockets with the given inodes, by reading the links in `/proc/<pid>/fd`.
+/// Processes whose descriptors cannot be read, e.g. those of other users, are skipped.
+pub(crate) fn socket_owners(inodes: &HashSet<u64>) -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return owners;
+    };
+    for proc in procs.flatten() {
+        let Some(pid) = proc.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        let Ok(fds) = fs::read_dir(proc.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(link) = fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = link
+                .to_str()
+                .and_then(|link| link.strip_prefix("socket:["))
+                .and_then(|link| link.strip_suffix(']'))
+                .and_then(|inode| inode.parse().ok());
+            if let Some(inode) = inode.filter(|inode| inodes.contains(inode)) {
+                owners.insert(inode, pid);
+            }
+        }
+    }
+    owners
+}
+
+/// Read the name of a process from `/proc/<pid>/comm`
+pub(crate) fn process_name(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+/// Parse the content of a socket table, skipping the header and malformed lines
+pub(crate) fn parse(content: &str, protocol: Protocol) -> Vec<SocketEntry> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| parse_line(line, protocol))
+        .collect()
+}
+
+fn parse_line(line: &str, protocol: Protocol) -> Option<SocketEntry> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    Some(SocketEntry {
+        protocol,
+        local: parse_addr(fields[1])?,
+        remote: parse_addr(fields[2])?,
+        state: u8::from_str_radix(fields[3], 16).ok()?,
+        inode: fields[9].parse().ok()?,
+    })
+}
+
+/// Parse an address like `0100007F:1F90`.
+/// The kernel prints the address as 32-bit words in host byte order, and the port in hex.
+fn parse_addr(field: &str) -> Option<SocketAddr> {
+    let (ip, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+    let ip: IpAddr = match ip.len() {
+        8 => Ipv4Addr::from(u32::from_str_radix(ip, 16).ok()?.to_ne_bytes()).into(),
+        32 => {
+            let mut octets = [0u8; 16];
+            for (i, chunk) in octets.chunks_mut(4).enumerate() {
+                let word = u32::from_str_radix(&ip[i * 8..i * 8 + 8], 16).ok()?;
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            Ipv6Addr::from(octets).into()
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_parse() {
+        let content = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4312 1 0000000000000000 100 0 0 10 0
+   1: 00000000000000000000000001000000:0BB8 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4313 1 0000000000000000 100 0 0 10 0
+   2: malformed";
+        let entries = parse(content, Protocol::Tcp);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].local, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(entries[0].state, 0x0A);
+        assert_eq!(entries[0].inode, 4312);
+        assert_eq!(entries[1].local, "[::1]:3000".parse().unwrap());
+
+        let table = SocketTable { entries };
+        let hosts = |ip: &str| vec![Host::from(ip.parse::<IpAddr>().unwrap())];
+        let (loopback, any, other) = (hosts("127.0.0.1"), hosts("0.0.0.0"), hosts("10.0.0.1"));
+        assert!(!table.is_free_in_hosts(8080, &loopback, &Protocol::All));
+        assert!(!table.is_free_in_hosts(8080, &any, &Protocol::Tcp));
+        assert!(table.is_free_in_hosts(8080, &other, &Protocol::Tcp));
+        assert!(table.is_free_in_hosts(8080, &loopback, &Protocol::Udp));
+        assert!(table.is_free_in_hosts(3000, &loopback, &Protocol::Tcp));
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+};
+
+use crate::{error::Result, utils::Host, Protocol};
+
+/// Which of the options a picked port came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickSource {
+    /// The first preferred port.
+    Exact,
+    /// A fallback preferred port, with its position in the preference list.
+    Fallback(usize),
+    /// The range, as no preferred port was free.
+    Range,
+}
+
+/// A picked port and where it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Picked {
+    pub port: u16,
+    pub source: PickSource,
+}
+
+/// What binding a port found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// The port could be bound.
+    Free,
+    /// The port is in use, or binding it failed with an unexpected error.
+    InUse,
+    /// No port can be bound on the address, e.g. it is not available or a link-local address without its scope.
+    Unprobeable(ErrorKind),
+    /// The process is not allowed to bind the port.
+    Denied,
+}
+
+impl ProbeOutcome {
+    pub fn is_free(&self) -> bool {
+        *self == ProbeOutcome::Free
+    }
+}
+
+impl From<ErrorKind> for ProbeOutcome {
+    /// Classifies the error binding a port failed with.
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::PermissionDenied => ProbeOutcome::Denied,
+            ErrorKind::AddrNotAvailable | ErrorKind::InvalidInput => {
+                ProbeOutcome::Unprobeable(kind)
+            }
+            _ => ProbeOutcome::InUse,
+        }
+    }
+}
+
+impl fmt::Display for ProbeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeOutcome::Free => write!(f, "free"),
+            ProbeOutcome::InUse => write!(f, "in use"),
+            ProbeOutcome::Unprobeable(kind) => write!(f, "unprobeable ({})", kind),
+            ProbeOutcome::Denied => write!(f, "permission denied"),
+        }
+    }
+}
+
+/// Why a port is not free on a host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeFailure {
+    pub host: IpAddr,
+    /// The index of the interface scoping an Ipv6 link-local host, 0 for other hosts.
+    pub scope_id: u32,
+    pub protocol: Protocol,
+    /// The error binding the port failed with, or `ErrorKind::AddrInUse` if the port is listed in the socket tables.
+    pub kind: ErrorKind,
+}
+
+impl ProbeFailure {
+    /// Classifies the error binding the port failed with.
+    pub fn outcome(&self) -> ProbeOutcome {
+        self.kind.into()
+    }
+
+    pub(crate) fn host(&self) -> Host {
+        Host {
+            ip: self.host,
+            scope_id: self.scope_id,
+        }
+    }
+}
+
+/// A port tried while picking
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub port: u16,
+    /// The hosts on which the port is not free, empty if it is free on every host.
+    pub failures: Vec<ProbeFailure>,
+    /// Whether the port was free but held by a lease of another process.
+    pub leased: bool,
+}
+
+impl Attempt {
+    /// Returns whether the port was free on every host.
+    pub fn is_free(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The result of a pick, with every port tried on the way
+#[derive(Debug)]
+pub struct PickReport {
+    pub result: Result<u16>,
+    /// The hosts every port was checked on, as addresses with port 0 keeping the scope id of Ipv6 link-local hosts.
+    pub hosts: Vec<SocketAddr>,
+    /// The hosts left out because no port can be bound on them, with the error binding failed with.
+    pub unprobeable: Vec<ProbeFailure>,
+    /// The ports tried, in order.
+    pub attempts: Vec<Attempt>,
+}
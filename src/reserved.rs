@@ -0,0 +1,70 @@
+use std::{
+    io,
+    net::{TcpListener, UdpSocket},
+};
+
+use crate::{utils::Host, Protocol};
+
+/// A port that is kept bound until the caller takes or releases it.
+///
+/// The sockets are bound on every host the port was reserved on, one for each host and protocol requested.
+/// Dropping the reservation closes the sockets and frees the port.
+#[derive(Debug)]
+pub struct ReservedPort {
+    port: u16,
+    tcp: Vec<TcpListener>,
+    udp: Vec<UdpSocket>,
+}
+
+impl ReservedPort {
+    /// Binds the sockets required by `protocol` on `host:port` for each of `hosts`.
+    pub(crate) fn bind(port: u16, hosts: &[Host], protocol: &Protocol) -> io::Result<Self> {
+        let mut reserved = ReservedPort {
+            port,
+            tcp: Vec::new(),
+            udp: Vec::new(),
+        };
+        for host in hosts {
+            let socket_addr = host.socket_addr(port);
+            if let Protocol::All | Protocol::Tcp = protocol {
+                reserved.tcp.push(TcpListener::bind(socket_addr)?);
+            }
+            if let Protocol::All | Protocol::Udp = protocol {
+                reserved.udp.push(UdpSocket::bind(socket_addr)?);
+            }
+        }
+        Ok(reserved)
+    }
+
+    /// Returns the reserved port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Takes the TCP listener of the first host, closing the other sockets.
+    /// Returns `None` if the port was not reserved for TCP.
+    pub fn into_tcp_listener(self) -> Option<TcpListener> {
+        self.tcp.into_iter().next()
+    }
+
+    /// Takes the UDP socket of the first host, closing the other sockets.
+    /// Returns `None` if the port was not reserved for UDP.
+    pub fn into_udp_socket(self) -> Option<UdpSocket> {
+        self.udp.into_iter().next()
+    }
+
+    /// Takes both sockets of the first host, closing the sockets of the other hosts.
+    pub fn into_sockets(self) -> (Option<TcpListener>, Option<UdpSocket>) {
+        (self.tcp.into_iter().next(), self.udp.into_iter().next())
+    }
+
+    /// Takes the sockets of every host, in the order the hosts were specified.
+    pub fn into_all_sockets(self) -> (Vec<TcpListener>, Vec<UdpSocket>) {
+        (self.tcp, self.udp)
+    }
+
+    /// Closes the sockets and returns the port, which is free to be bound again.
+    pub fn release(self) -> u16 {
+        self.port
+    }
+}
@@ -0,0 +1,47 @@
+use std::{collections::HashSet, fs};
+
+use crate::Protocol;
+
+const SERVICES: &str = "/etc/services";
+
+/// Read the ports documented in the services database for `protocol`, empty if it cannot be read
+pub(crate) fn service_ports(protocol: &Protocol) -> HashSet<u16> {
+    fs::read_to_string(SERVICES)
+        .map(|content| parse(&content, protocol))
+        .unwrap_or_default()
+}
+
+/// Parse lines like `http 80/tcp www # WorldWideWeb HTTP`, keeping the ports of `protocol`
+fn parse(content: &str, protocol: &Protocol) -> HashSet<u16> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next()?;
+            let (port, proto) = line.split_whitespace().nth(1)?.split_once('/')?;
+            let matches = match protocol {
+                Protocol::All => true,
+                Protocol::Tcp => proto.eq_ignore_ascii_case("tcp"),
+                Protocol::Udp => proto.eq_ignore_ascii_case("udp"),
+            };
+            matches.then(|| port.parse().ok()).flatten()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "# Network services
+http		80/tcp		www		# WorldWideWeb HTTP
+postgresql	5432/tcp	postgres
+syslog		514/udp
+broken
+";
+        assert_eq!(parse(content, &Protocol::All), [80, 5432, 514].into());
+        assert_eq!(parse(content, &Protocol::Tcp), [80, 5432].into());
+        assert_eq!(parse(content, &Protocol::Udp), [514].into());
+    }
+}
@@ -0,0 +1,197 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rand::RngCore;
+
+use crate::{permutation::Permutation, PortSet};
+
+/// The order in which the ports of the range are tried.
+///
+/// Implement it to plug a custom ordering into `PortPicker::strategy`.
+/// Ports outside the port set and excluded ports are skipped by the picker.
+///
+/// #Examples:
+///
+/// ```
+/// use random_port::{PortPicker, PortSet, SelectionStrategy};
+/// use rand::RngCore;
+///
+/// /// Tries even ports only.
+/// struct Even;
+///
+/// impl SelectionStrategy for Even {
+///     fn candidates(
+///         &self,
+///         ports: PortSet,
+///         _rng: &mut dyn RngCore,
+///     ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+///         Box::new(ports.into_iter().filter(|port| port % 2 == 0))
+///     }
+/// }
+///
+/// let port = PortPicker::new().strategy(Even).pick().unwrap();
+/// assert_eq!(port % 2, 0);
+/// ```
+pub trait SelectionStrategy: Send + Sync {
+    /// Returns the ports of the set in the order they should be tried.
+    /// `rng` is the picker's random number generator, seeded by `PortPicker::seed` or `PortPicker::rng`.
+    fn candidates(
+        &self,
+        ports: PortSet,
+        rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_>;
+}
+
+/// Tries the ports in ascending order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sequential;
+
+impl SelectionStrategy for Sequential {
+    fn candidates(
+        &self,
+        ports: PortSet,
+        _rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+        Box::new(ports.into_iter())
+    }
+}
+
+/// Tries the ports in descending order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Descending;
+
+impl SelectionStrategy for Descending {
+    fn candidates(
+        &self,
+        ports: PortSet,
+        _rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+        Box::new(ports.into_iter().rev())
+    }
+}
+
+/// Tries every port of the set exactly once, in a random order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Random;
+
+impl SelectionStrategy for Random {
+    fn candidates(
+        &self,
+        ports: PortSet,
+        rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+        let permutation = Permutation::new(ports.len() as u64, rng);
+        Box::new(permutation.filter_map(move |index| ports.get(index as usize)))
+    }
+}
+
+/// Resumes after the last port handed out by the previous call, wrapping around the set.
+///
+/// When ports are probed in parallel, ports probed ahead of the picked one are also skipped by the next call.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicU32,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SelectionStrategy for RoundRobin {
+    fn candidates(
+        &self,
+        ports: PortSet,
+        _rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+        let first = ports.rank(self.next.load(Ordering::Relaxed));
+        let indexes = (first..ports.len()).chain(0..first);
+        let ports = indexes
+            .filter_map(move |index| ports.get(index))
+            .inspect(move |port| self.next.store(*port as u32 + 1, Ordering::Relaxed));
+        Box::new(ports)
+    }
+}
+
+/// Tries the preferred port first, then the ports closest to it, alternating above and below.
+#[derive(Debug, Clone, Copy)]
+pub struct ClosestTo(pub u16);
+
+impl SelectionStrategy for ClosestTo {
+    fn candidates(
+        &self,
+        ports: PortSet,
+        _rng: &mut dyn RngCore,
+    ) -> Box<dyn Iterator<Item = u16> + Send + '_> {
+        let (Some(start), Some(end)) = (ports.first(), ports.last()) else {
+            return Box::new(std::iter::empty());
+        };
+        let (start, end) = (start as i32, end as i32);
+        let preferred = (self.0 as i32).clamp(start, end);
+        let max_distance = (preferred - start).max(end - preferred);
+        let ports = (0..=max_distance)
+            .flat_map(move |distance| {
+                let above = Some(preferred + distance);
+                let below = (distance > 0).then_some(preferred - distance);
+                above.into_iter().chain(below)
+            })
+            .filter(move |port| (start..=end).contains(port))
+            .map(|port| port as u16)
+            .filter(move |port| ports.contains(*port));
+        Box::new(ports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(strategy: &dyn SelectionStrategy, ports: impl Into<PortSet>) -> Vec<u16> {
+        strategy
+            .candidates(ports.into(), &mut rand::thread_rng())
+            .collect()
+    }
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(order(&Sequential, 3000..=3003), [3000, 3001, 3002, 3003]);
+        assert_eq!(order(&Descending, 3000..=3003), [3003, 3002, 3001, 3000]);
+        assert_eq!(
+            order(&ClosestTo(3001), 3000..=3004),
+            [3001, 3002, 3000, 3003, 3004]
+        );
+        assert_eq!(order(&ClosestTo(80), 3000..=3002), [3000, 3001, 3002]);
+
+        let mut random = order(&Random, 3000..=3099);
+        random.sort_unstable();
+        assert_eq!(random, (3000..=3099).collect::<Vec<_>>());
+
+        let split: PortSet = "3000-3001,3008-3009".parse().unwrap();
+        let mut rng = rand::thread_rng();
+        assert_eq!(
+            Descending
+                .candidates(split.clone(), &mut rng)
+                .collect::<Vec<_>>(),
+            [3009, 3008, 3001, 3000]
+        );
+        assert_eq!(
+            ClosestTo(3004)
+                .candidates(split.clone(), &mut rng)
+                .collect::<Vec<_>>(),
+            [3001, 3008, 3000, 3009]
+        );
+
+        let round_robin = RoundRobin::new();
+        assert_eq!(
+            round_robin.candidates(split.clone(), &mut rng).next(),
+            Some(3000)
+        );
+        assert_eq!(
+            round_robin
+                .candidates(split.clone(), &mut rng)
+                .collect::<Vec<_>>(),
+            [3001, 3008, 3009, 3000]
+        );
+        assert_eq!(round_robin.candidates(split, &mut rng).next(), Some(3001));
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::HashSet, fs, ops::RangeInclusive};
+
+const EPHEMERAL_PORT_RANGE: &str = "/proc/sys/net/ipv4/ip_local_port_range";
+const RESERVED_PORTS: &str = "/proc/sys/net/ipv4/ip_local_reserved_ports";
+#[cfg(target_os = "linux")]
+const UNPRIVILEGED_PORT_START: &str = "/proc/sys/net/ipv4/ip_unprivileged_port_start";
+#[cfg(target_os = "linux")]
+const PROCESS_STATUS: &str = "/proc/self/status";
+
+/// The bit of `CAP_NET_BIND_SERVICE` in the capability sets of `/proc/<pid>/status`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const CAP_NET_BIND_SERVICE: u32 = 10;
+
+/// The default ephemeral port range of Linux
+#[cfg(target_os = "linux")]
+const DEFAULT_EPHEMERAL_PORT_RANGE: RangeInclusive<u16> = 32768..=60999;
+
+/// The ephemeral port range suggested by IANA, used by macOS and Windows
+#[cfg(not(target_os = "linux"))]
+const DEFAULT_EPHEMERAL_PORT_RANGE: RangeInclusive<u16> = 49152..=65535;
+
+/// Read the range the kernel picks the source ports of outgoing connections from,
+/// falling back to the platform default if it cannot be read.
+pub(crate) fn ephemeral_port_range() -> RangeInclusive<u16> {
+    fs::read_to_string(EPHEMERAL_PORT_RANGE)
+        .ok()
+        .and_then(|content| parse_port_range(&content))
+        .unwrap_or(DEFAULT_EPHEMERAL_PORT_RANGE)
+}
+
+/// Read the ports reserved through `net.ipv4.ip_local_reserved_ports`, empty if it cannot be read
+pub(crate) fn reserved_ports() -> HashSet<u16> {
+    fs::read_to_string(RESERVED_PORTS)
+        .map(|content| parse_port_list(&content))
+        .unwrap_or_default()
+}
+
+/// The lowest port the process can bind.
+/// On Linux this is 1 with `CAP_NET_BIND_SERVICE`, which root has unless it was dropped,
+/// otherwise `net.ipv4.ip_unprivileged_port_start`, 1024 if it cannot be read.
+/// Other platforms are not checked, binding reports a permission error instead.
+#[cfg(target_os = "linux")]
+pub(crate) fn lowest_bindable_port() -> u16 {
+    let capable = fs::read_to_string(PROCESS_STATUS)
+        .ok()
+        .and_then(|content| parse_effective_capabilities(&content))
+        .is_some_and(|caps| caps & (1 << CAP_NET_BIND_SERVICE) != 0);
+    if capable {
+        return 1;
+    }
+    fs::read_to_string(UNPRIVILEGED_PORT_START)
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+        .unwrap_or(1024)
+        .max(1)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn lowest_bindable_port() -> u16 {
+    1
+}
+
+/// Parse the effective capability set from the hex `CapEff` line of `/proc/<pid>/status`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_effective_capabilities(content: &str) -> Option<u64> {
+    let caps = content
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(caps.trim(), 16).ok()
+}
+
+/// Parse a comma separated list of ports and ranges, e.g. `8000-8010,9000`, skipping malformed items
+fn parse_port_list(content: &str) -> HashSet<u16> {
+    let mut ports = HashSet::new();
+    for item in content.trim().split(',').map(str::trim) {
+        let (start, end) = item.split_once('-').unwrap_or((item, item));
+        if let (Ok(start), Ok(end)) = (start.parse::<u16>(), end.parse::<u16>()) {
+            ports.extend(start..=end);
+        }
+    }
+    ports
+}
+
+/// Parse two ports separated by whitespace, e.g. `32768\t60999`
+fn parse_port_range(content: &str) -> Option<RangeInclusive<u16>> {
+    let mut ports = content.split_whitespace();
+    let start = ports.next()?.parse().ok()?;
+    let end = ports.next()?.parse().ok()?;
+    Some(start..=end).filter(|range| !range.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("32768\t60999\n"), Some(32768..=60999));
+        assert_eq!(parse_port_range("60999 32768"), None);
+        assert_eq!(parse_port_range("32768"), None);
+        assert!(!ephemeral_port_range().is_empty());
+    }
+
+    #[test]
+    fn test_parse_port_list() {
+        let ports = parse_port_list("8000-8002, 9000,bad\n");
+        assert_eq!(ports, [8000, 8001, 8002, 9000].into());
+        assert!(parse_port_list("\n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_effective_capabilities() {
+        let status = "Name:\tcat\nCapPrm:\t0000000000000000\nCapEff:\t0000000000000400\n";
+        let caps = parse_effective_capabilities(status).unwrap();
+        assert_eq!(
+            caps & (1 << CAP_NET_BIND_SERVICE),
+            1 << CAP_NET_BIND_SERVICE
+        );
+        assert_eq!(parse_effective_capabilities("Name:\tcat\n"), None);
+        assert!(lowest_bindable_port() >= 1);
+    }
+}
@@ -1,73 +1,598 @@
 use std::{
-    collections::HashSet,
-    io::ErrorKind,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket},
+    collections::{BTreeMap, HashSet},
+    fmt,
+    io::{self, ErrorKind},
+    net::{
+        IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpListener, ToSocketAddrs, UdpSocket,
+    },
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
 };
 
 use network_interface::{NetworkInterface, NetworkInterfaceConfig};
 
-use crate::Protocol;
+use crate::{
+    error::{Errors, Result},
+    procfs::SocketTable,
+    report::{ProbeFailure, ProbeOutcome},
+    Detection, OutcomePolicy, Protocol,
+};
+
+/// How many ports were checked while walking the candidates, and why they were not free
+#[derive(Debug, Default)]
+pub(crate) struct Tally {
+    pub(crate) checked: usize,
+    pub(crate) denied: usize,
+    /// The port and error that failed the pick, as the policies require
+    pub(crate) failed: Option<(u16, ErrorKind)>,
+}
+
+impl Tally {
+    pub(crate) fn record(&mut self, failure: Option<ErrorKind>) {
+        self.checked += 1;
+        if failure == Some(ErrorKind::PermissionDenied) {
+            self.denied += 1;
+        }
+    }
+
+    /// Record a failure of `port` if the policies fail the pick on it, returning whether they do
+    pub(crate) fn fails(
+        &mut self,
+        port: u16,
+        failure: Option<ErrorKind>,
+        policies: &OutcomePolicies,
+    ) -> bool {
+        let Some(kind) = failure.filter(|kind| policies.fails(*kind)) else {
+            return false;
+        };
+        self.failed = Some((port, kind));
+        true
+    }
+}
+
+/// How the outcomes of probing a port that are neither free nor in use affect the pick
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OutcomePolicies {
+    pub(crate) unprobeable: OutcomePolicy,
+    pub(crate) denied: OutcomePolicy,
+}
+
+impl Default for OutcomePolicies {
+    fn default() -> Self {
+        OutcomePolicies {
+            unprobeable: OutcomePolicy::Free,
+            denied: OutcomePolicy::InUse,
+        }
+    }
+}
+
+impl OutcomePolicies {
+    pub(crate) fn policy(&self, outcome: ProbeOutcome) -> OutcomePolicy {
+        match outcome {
+            ProbeOutcome::Free => OutcomePolicy::Free,
+            ProbeOutcome::InUse => OutcomePolicy::InUse,
+            ProbeOutcome::Unprobeable(_) => self.unprobeable,
+            ProbeOutcome::Denied => self.denied,
+        }
+    }
+
+    /// Whether the error binding a port failed with fails the pick
+    pub(crate) fn fails(&self, kind: ErrorKind) -> bool {
+        self.policy(kind.into()) == OutcomePolicy::Fail
+    }
+}
+
+/// An address to check ports on, with the interface index scoping Ipv6 link-local addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Host {
+    pub(crate) ip: IpAddr,
+    pub(crate) scope_id: u32,
+}
+
+impl Host {
+    /// A host on the interface with index `scope_id`, which is only kept for Ipv6 link-local addresses
+    pub(crate) fn scoped(ip: IpAddr, scope_id: u32) -> Self {
+        let scope_id = match ip {
+            IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => scope_id,
+            _ => 0,
+        };
+        Host { ip, scope_id }
+    }
+
+    pub(crate) fn socket_addr(&self, port: u16) -> SocketAddr {
+        match self.ip {
+            IpAddr::V6(ip) => SocketAddrV6::new(ip, port, 0, self.scope_id).into(),
+            IpAddr::V4(_) => SocketAddr::new(self.ip, port),
+        }
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope_id {
+            0 => write!(f, "{}", self.ip),
+            scope_id => write!(f, "{}%{}", self.ip, scope_id),
+        }
+    }
+}
+
+impl From<IpAddr> for Host {
+    fn from(ip: IpAddr) -> Self {
+        Host { ip, scope_id: 0 }
+    }
+}
+
+impl From<SocketAddr> for Host {
+    fn from(socket_addr: SocketAddr) -> Self {
+        match socket_addr {
+            SocketAddr::V6(addr) => Host::scoped((*addr.ip()).into(), addr.scope_id()),
+            SocketAddr::V4(addr) => IpAddr::from(*addr.ip()).into(),
+        }
+    }
+}
+
+/// Check if ports are free on a set of hosts with the selected detection strategy
+pub(crate) struct Prober {
+    hosts: Vec<Host>,
+    unprobeable: Vec<ProbeFailure>,
+    protocol: Protocol,
+    policies: OutcomePolicies,
+    bind: bool,
+    table: Option<SocketTable>,
+    /// The ports being bound by a thread, so that concurrent probes of a port do not make each other fail
+    probing: Mutex<HashSet<u16>>,
+    probed: Condvar,
+}
+
+impl Prober {
+    /// Create a prober, reading the socket tables once if the strategy needs them.
+    /// Falls back to bind probing if the tables cannot be read.
+    /// Hosts on which no port can be bound are left out and listed as unprobeable.
+    pub(crate) fn new(
+        mut hosts: Vec<Host>,
+        protocol: Protocol,
+        detection: Detection,
+        policies: OutcomePolicies,
+    ) -> Self {
+        let unprobeable: Vec<ProbeFailure> = hosts
+            .iter()
+            .filter_map(|host| unprobeable(host, &protocol))
+            .collect();
+        hosts.retain(|host| !unprobeable.iter().any(|failure| failure.host() == *host));
+        for failure in &unprobeable {
+            trace_unprobeable(failure);
+        }
+        let table = match detection {
+            Detection::Bind => None,
+            Detection::Procfs | Detection::Both => SocketTable::read().ok(),
+        };
+        let bind = detection != Detection::Procfs || table.is_none();
+        Prober {
+            hosts,
+            unprobeable,
+            protocol,
+            policies,
+            bind,
+            table,
+            probing: Mutex::new(HashSet::new()),
+            probed: Condvar::new(),
+        }
+    }
+
+    /// The hosts to check, in the order they were specified
+    pub(crate) fn hosts(&self) -> &[Host] {
+        &self.hosts
+    }
+
+    /// The hosts left out because no port can be bound on them, with the error binding failed with
+    pub(crate) fn unprobeable(&self) -> &[ProbeFailure] {
+        &self.unprobeable
+    }
+
+    /// Check if a port is free in all hosts, returning the first error found if it is not
+    pub(crate) fn check(&self, port: u16) -> Option<ErrorKind> {
+        if !self.is_free_in_table(port) {
+            return Some(ErrorKind::AddrInUse);
+        }
+        if !self.bind {
+            return None;
+        }
+        self.exclusive(port, || {
+            first_failure(port, &self.hosts, &self.protocol, &self.policies)
+        })
+        .map(|failure| failure.kind)
+    }
+
+    /// Check if the `len` ports from `start` are all free in all hosts, returning the first error found if not
+    pub(crate) fn check_block(&self, start: u16, len: u16) -> Option<ErrorKind> {
+        (start..=start + (len - 1)).find_map(|port| self.check(port))
+    }
+
+    /// Run a probe of `port` once no other thread is probing it
+    fn exclusive<T>(&self, port: u16, probe: impl FnOnce() -> T) -> T {
+        let mut probing = self.probing.lock().unwrap_or_else(|err| err.into_inner());
+        while probing.contains(&port) {
+            probing = self
+                .probed
+                .wait(probing)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        probing.insert(port);
+        drop(probing);
+        let result = probe();
+        self.probing
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&port);
+        self.probed.notify_all();
+        result
+    }
+
+    /// Check a port on every host without stopping at the first failure
+    pub(crate) fn probe(&self, port: u16) -> Vec<ProbeFailure> {
+        let mut failures = Vec::new();
+        for host in &self.hosts {
+            let in_table = self
+                .table
+                .as_ref()
+                .is_some_and(|table| !table.is_free(port, &host.ip, &self.protocol));
+            if in_table {
+                failures.push(ProbeFailure {
+                    host: host.ip,
+                    scope_id: host.scope_id,
+                    protocol: self.protocol,
+                    kind: ErrorKind::AddrInUse,
+                });
+            } else if self.bind {
+                failures.extend(probe(port, host, &self.protocol, &self.policies));
+            }
+        }
+        failures
+    }
+
+    /// Check if a port is free in the socket tables, always true if they are not consulted
+    pub(crate) fn is_free_in_table(&self, port: u16) -> bool {
+        self.table
+            .as_ref()
+            .is_none_or(|table| table.is_free_in_hosts(port, &self.hosts, &self.protocol))
+    }
+
+    /// Whether ports must still be probed by binding them
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn binds(&self) -> bool {
+        self.bind
+    }
+
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub(crate) fn policies(&self) -> &OutcomePolicies {
+        &self.policies
+    }
+}
+
+/// Which network interfaces to check, by name or glob pattern such as `veth*`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InterfaceFilter {
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+}
+
+impl InterfaceFilter {
+    /// Whether every interface is checked
+    pub(crate) fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
 
-/// Get all available network interfaces
-pub(crate) fn get_local_hosts() -> HashSet<IpAddr> {
-    let mut result = HashSet::<IpAddr>::new();
-    result.insert(Ipv4Addr::UNSPECIFIED.into());
-    result.insert(Ipv6Addr::UNSPECIFIED.into());
-    let interfaces = NetworkInterface::show().unwrap();
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, name));
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// Get the addresses of the network interfaces selected by the filter, link-local addresses scoped to their interface.
+/// The unspecified addresses are only included if every interface is checked.
+pub(crate) fn get_local_hosts(filter: &InterfaceFilter) -> Result<Vec<Host>> {
+    let mut result = Vec::new();
+    if filter.is_empty() {
+        result.push(IpAddr::from(Ipv4Addr::UNSPECIFIED).into());
+        result.push(IpAddr::from(Ipv6Addr::UNSPECIFIED).into());
+    }
+    let interfaces = NetworkInterface::show().map_err(|err| Errors::Interfaces(err.into()))?;
     for interface in interfaces {
+        if !filter.matches(&interface.name) {
+            continue;
+        }
         for addr in interface.addr {
-            result.insert(addr.ip());
+            push_host(&mut result, Host::scoped(addr.ip(), interface.index));
         }
     }
-    result
+    if result.is_empty() {
+        return Err(Errors::InvalidOption(
+            "No address found on the selected network interfaces".to_string(),
+        ));
+    }
+    Ok(result)
 }
 
-/// Check if a port is free in all hosts
-pub(crate) fn is_free_in_hosts(port: u16, hosts: &HashSet<IpAddr>, protocol: &Protocol) -> bool {
-    for host in hosts {
-        if !is_free(port, host, protocol) {
-            println!("Port {} is not free in {}", port, host);
-            return false;
+/// Match a name against a glob pattern, where `*` matches any characters and `?` matches one
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
         }
     }
-    true
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
-/// Check if a port is free
-pub(crate) fn is_free(port: u16, host: &IpAddr, protocol: &Protocol) -> bool {
-    match protocol {
-        Protocol::Tcp => is_free_tcp(port, host),
-        Protocol::Udp => is_free_udp(port, host),
-        Protocol::All => is_free_tcp(port, host) && is_free_udp(port, host),
+/// Resolve a host to its addresses, either an IP address or a name looked up in `/etc/hosts` and the system resolver.
+/// A link-local address keeps its scope, e.g. `fe80::1%eth0`.
+pub(crate) fn resolve_host(host: &str) -> Result<Vec<Host>> {
+    if let Ok(ip_addr) = host.parse::<IpAddr>() {
+        return Ok(vec![ip_addr.into()]);
+    }
+    let resolution_error = |source| Errors::HostResolution {
+        host: host.to_string(),
+        source,
+    };
+    let mut ip_addrs = Vec::new();
+    for socket_addr in (host, 0).to_socket_addrs().map_err(resolution_error)? {
+        push_host(&mut ip_addrs, socket_addr.into());
+    }
+    if ip_addrs.is_empty() {
+        return Err(resolution_error(io::Error::new(
+            ErrorKind::NotFound,
+            "no addresses found",
+        )));
     }
+    Ok(ip_addrs)
 }
 
-/// Check if a TCP port is free
-pub(crate) fn is_free_tcp(port: u16, host: &IpAddr) -> bool {
-    let socket_addr = SocketAddr::new(*host, port);
-    let result = TcpListener::bind(socket_addr);
-    if let Ok(_) = result {
-        return true;
+/// Add a host to a list unless it is already in it, keeping the order hosts were found in
+pub(crate) fn push_host(hosts: &mut Vec<Host>, host: Host) {
+    if !hosts.contains(&host) {
+        hosts.push(host);
     }
-    let err = result.unwrap_err();
-    if err.kind() == ErrorKind::AddrNotAvailable || err.kind() == ErrorKind::InvalidInput {
-            return true;
-        }
-    false
 }
 
-/// Check if a UDP port is free
-pub(crate) fn is_free_udp(port: u16, host: &IpAddr) -> bool {
-    let socket_addr = SocketAddr::new(*host, port);
-    let result = UdpSocket::bind(socket_addr);
-    if let Ok(_) = result {
-        return true;
+/// A filter letting each port through the first time only
+pub(crate) fn first_seen() -> impl FnMut(&u16) -> bool + Send {
+    let mut seen = vec![0u64; 1 << 10];
+    move |port| {
+        let (word, bit) = (*port as usize / 64, 1 << (port % 64));
+        let first = seen[word] & bit == 0;
+        seen[word] |= bit;
+        first
     }
-    let err = result.unwrap_err();
-    if err.kind() == ErrorKind::AddrNotAvailable || err.kind() == ErrorKind::InvalidInput {
-        return true;
+}
+
+/// Hash a key with 64-bit FNV-1a, which unlike `DefaultHasher` is stable across runs and Rust versions
+pub(crate) fn key_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Walk the candidates that `check` finds free in order until `accept` returns a value.
+/// If none is accepted, or the policies fail the pick on a port, returns how many ports were checked.
+///
+/// With more than one thread, workers check the candidates ahead of the caller,
+/// and the results are handed to `accept` in candidate order.
+pub(crate) fn find_free<T>(
+    candidates: Box<dyn Iterator<Item = u16> + Send + '_>,
+    prober: &Prober,
+    threads: usize,
+    check: impl Fn(u16) -> Option<ErrorKind> + Sync,
+    mut accept: impl FnMut(u16) -> Option<T>,
+) -> std::result::Result<T, Tally> {
+    let mut tally = Tally::default();
+    if threads <= 1 {
+        for port in candidates {
+            let failure = check(port);
+            tally.record(failure);
+            if tally.fails(port, failure, prober.policies()) {
+                return Err(tally);
+            }
+            if failure.is_some() {
+                continue;
+            }
+            if let Some(found) = accept(port) {
+                return Ok(found);
+            }
+        }
+        return Err(tally);
     }
-    false
+    let candidates = Mutex::new(candidates.enumerate());
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (candidates, done, check) = (&candidates, &done, &check);
+            scope.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let next = candidates.lock().unwrap().next();
+                    let Some((index, port)) = next else {
+                        break;
+                    };
+                    let failure = check(port);
+                    if sender.send((index, port, failure)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        for (index, port, failure) in receiver {
+            pending.insert(index, (port, failure));
+            while let Some((port, failure)) = pending.remove(&next_index) {
+                next_index += 1;
+                tally.record(failure);
+                if tally.fails(port, failure, prober.policies()) {
+                    done.store(true, Ordering::Relaxed);
+                    return Err(tally);
+                }
+                if failure.is_some() {
+                    continue;
+                }
+                if let Some(found) = accept(port) {
+                    done.store(true, Ordering::Relaxed);
+                    return Ok(found);
+                }
+            }
+        }
+        Err(tally)
+    })
+}
+
+/// Check if a port is free in all hosts, returning the first failure found if it is not
+pub(crate) fn first_failure(
+    port: u16,
+    hosts: &[Host],
+    protocol: &Protocol,
+    policies: &OutcomePolicies,
+) -> Option<ProbeFailure> {
+    let failure = hosts
+        .iter()
+        .find_map(|host| probe(port, host, protocol, policies))?;
+    trace_failure(port, &failure);
+    Some(failure)
+}
+
+/// Emit a diagnostic event for a port that is not free
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn trace_failure(port: u16, failure: &ProbeFailure) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        port,
+        host = %failure.host,
+        scope_id = failure.scope_id,
+        protocol = ?failure.protocol,
+        kind = ?failure.kind,
+        "port is not free"
+    );
+}
+
+/// Emit a diagnostic event for a host that is left out because no port can be bound on it
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn trace_unprobeable(failure: &ProbeFailure) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        host = %failure.host,
+        scope_id = failure.scope_id,
+        protocol = ?failure.protocol,
+        kind = ?failure.kind,
+        "host cannot be probed"
+    );
+}
+
+/// Check if a port is free, returning why it is not
+pub(crate) fn probe(
+    port: u16,
+    host: &Host,
+    protocol: &Protocol,
+    policies: &OutcomePolicies,
+) -> Option<ProbeFailure> {
+    let tcp = || probe_tcp(port, host, policies).map(|kind| (Protocol::Tcp, kind));
+    let udp = || probe_udp(port, host, policies).map(|kind| (Protocol::Udp, kind));
+    let failure = match protocol {
+        Protocol::Tcp => tcp(),
+        Protocol::Udp => udp(),
+        Protocol::All => tcp().or_else(udp),
+    };
+    failure.map(|(protocol, kind)| ProbeFailure {
+        host: host.ip,
+        scope_id: host.scope_id,
+        protocol,
+        kind,
+    })
+}
+
+/// Check if a TCP port is free, returning the error binding it failed with
+pub(crate) fn probe_tcp(port: u16, host: &Host, policies: &OutcomePolicies) -> Option<ErrorKind> {
+    bind_error(TcpListener::bind(host.socket_addr(port)).err(), policies)
+}
+
+/// Check if a UDP port is free, returning the error binding it failed with
+pub(crate) fn probe_udp(port: u16, host: &Host, policies: &OutcomePolicies) -> Option<ErrorKind> {
+    bind_error(UdpSocket::bind(host.socket_addr(port)).err(), policies)
+}
+
+/// Bind a port on an address and classify the result
+pub(crate) fn probe_outcome(socket_addr: SocketAddr, protocol: &Protocol) -> ProbeOutcome {
+    let tcp = || TcpListener::bind(socket_addr).err();
+    let udp = || UdpSocket::bind(socket_addr).err();
+    let err = match protocol {
+        Protocol::Tcp => tcp(),
+        Protocol::Udp => udp(),
+        Protocol::All => tcp().or_else(udp),
+    };
+    err.map_or(ProbeOutcome::Free, |err| err.kind().into())
+}
+
+/// Check whether any port can be bound on a host by binding port 0,
+/// returning why not if the address is not available or invalid, e.g. a link-local address without its scope.
+pub(crate) fn unprobeable(host: &Host, protocol: &Protocol) -> Option<ProbeFailure> {
+    let (protocol, err) = match protocol {
+        Protocol::Udp => (Protocol::Udp, UdpSocket::bind(host.socket_addr(0)).err()?),
+        Protocol::Tcp | Protocol::All => {
+            (Protocol::Tcp, TcpListener::bind(host.socket_addr(0)).err()?)
+        }
+    };
+    let kind = err.kind();
+    matches!(kind.into(), ProbeOutcome::Unprobeable(_)).then_some(ProbeFailure {
+        host: host.ip,
+        scope_id: host.scope_id,
+        protocol,
+        kind,
+    })
+}
+
+/// Bind port 0 and return the port the OS assigned, TCP unless the protocol is UDP.
+/// The socket is closed before returning.
+pub(crate) fn os_assigned_port(host: &Host, protocol: &Protocol) -> io::Result<u16> {
+    let socket_addr = host.socket_addr(0);
+    let local_addr = match protocol {
+        Protocol::Udp => UdpSocket::bind(socket_addr)?.local_addr()?,
+        Protocol::Tcp | Protocol::All => TcpListener::bind(socket_addr)?.local_addr()?,
+    };
+    Ok(local_addr.port())
+}
+
+/// The kind of a bind error unless the policies count its outcome as free.
+/// Addresses that cannot be bound at all are left out by `unprobeable` beforehand,
+/// should one become unavailable while picking the policies decide.
+pub(crate) fn bind_error(err: Option<io::Error>, policies: &OutcomePolicies) -> Option<ErrorKind> {
+    let kind = err?.kind();
+    (policies.policy(kind.into()) != OutcomePolicy::Free).then_some(kind)
 }
 
 #[cfg(test)]
@@ -76,7 +601,126 @@ mod test {
 
     #[test]
     fn test_get_local_hosts() {
-        let result = get_local_hosts();
-        assert!(result.len() > 0);
+        let result = get_local_hosts(&InterfaceFilter::default()).unwrap();
+        assert!(result.contains(&IpAddr::from(Ipv4Addr::UNSPECIFIED).into()));
+        for host in &result {
+            assert_eq!(unprobeable(host, &Protocol::All), None);
+        }
+
+        let loopback = InterfaceFilter {
+            include: vec!["lo*".to_string()],
+            exclude: Vec::new(),
+        };
+        if let Ok(result) = get_local_hosts(&loopback) {
+            assert!(result.iter().all(|host| host.ip.is_loopback()));
+        }
+
+        let none = InterfaceFilter {
+            include: Vec::new(),
+            exclude: vec!["*".to_string()],
+        };
+        assert!(matches!(
+            get_local_hosts(&none),
+            Err(Errors::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_scoped_host() {
+        let link_local: IpAddr = "fe80::1".parse().unwrap();
+        assert_eq!(Host::scoped(link_local, 2).scope_id, 2);
+        assert_eq!(Host::scoped(Ipv6Addr::LOCALHOST.into(), 2).scope_id, 0);
+        let socket_addr = Host::scoped(link_local, 2).socket_addr(8080);
+        assert_eq!(socket_addr, "[fe80::1%2]:8080".parse().unwrap());
+
+        assert_eq!(Host::scoped(link_local, 2).to_string(), "fe80::1%2");
+        assert_eq!(Host::scoped(link_local, 0).to_string(), "fe80::1");
+
+        let unscoped = Host::from(link_local);
+        let failure = unprobeable(&unscoped, &Protocol::Tcp).unwrap();
+        assert_eq!(failure.host, link_local);
+        assert_eq!(failure.scope_id, 0);
+
+        // Only the scope of a link-local address that cannot be probed is left out
+        let scoped = get_local_hosts(&InterfaceFilter::default())
+            .unwrap()
+            .into_iter()
+            .find(|host| host.scope_id != 0);
+        if let Some(scoped) = scoped {
+            let unscoped = Host::from(scoped.ip);
+            let prober = Prober::new(
+                vec![unscoped, scoped],
+                Protocol::Tcp,
+                Detection::Bind,
+                OutcomePolicies::default(),
+            );
+            assert_eq!(prober.hosts(), [scoped]);
+            assert_eq!(prober.unprobeable()[0].host(), unscoped);
+        }
+    }
+
+    #[test]
+    fn test_outcome_policies() {
+        let policies = OutcomePolicies::default();
+        let denied = || Some(io::Error::from(ErrorKind::PermissionDenied));
+        let unavailable = || Some(io::Error::from(ErrorKind::AddrNotAvailable));
+        assert_eq!(
+            bind_error(denied(), &policies),
+            Some(ErrorKind::PermissionDenied)
+        );
+        assert_eq!(bind_error(unavailable(), &policies), None);
+        assert!(!policies.fails(ErrorKind::PermissionDenied));
+
+        let policies = OutcomePolicies {
+            unprobeable: OutcomePolicy::Fail,
+            denied: OutcomePolicy::Free,
+        };
+        assert_eq!(bind_error(denied(), &policies), None);
+        assert_eq!(
+            bind_error(unavailable(), &policies),
+            Some(ErrorKind::AddrNotAvailable)
+        );
+        assert!(policies.fails(ErrorKind::InvalidInput));
+        assert!(!policies.fails(ErrorKind::AddrInUse));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("eth0", "eth0"));
+        assert!(!glob_match("eth0", "eth01"));
+        assert!(glob_match("veth*", "veth12ab"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("e?h*0", "eth10"));
+        assert!(glob_match("*a*b", "xaxab"));
+        assert!(!glob_match("docker?", "docker"));
+    }
+
+    #[test]
+    fn test_resolve_host() {
+        assert_eq!(
+            resolve_host("::1").unwrap(),
+            vec![IpAddr::from(Ipv6Addr::LOCALHOST).into()]
+        );
+        let localhost = resolve_host("localhost").unwrap();
+        assert!(localhost.iter().all(|host| host.ip.is_loopback()));
+        assert!(matches!(
+            resolve_host("not a host"),
+            Err(Errors::HostResolution { .. })
+        ));
+    }
+
+    #[test]
+    fn test_first_seen() {
+        let ports: Vec<u16> = [80, 65535, 80, 0, 65535, 81]
+            .into_iter()
+            .filter(first_seen())
+            .collect();
+        assert_eq!(ports, [80, 65535, 0, 81]);
+    }
+
+    #[test]
+    fn test_key_hash() {
+        assert_eq!(key_hash(""), 0xcbf29ce484222325);
+        assert_eq!(key_hash("a"), 0xaf63dc4c8601ec8c);
     }
 }